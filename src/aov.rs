@@ -0,0 +1,318 @@
+extern crate cgmath;
+
+use crate::framebuffer::Framebuffer;
+use crate::output::{self, ExrChannel};
+use crate::tonemap::OutputTransform;
+
+use anyhow::Result;
+use cgmath::{InnerSpace, Vector3};
+use std::{path::Path, str::FromStr};
+
+// Arbitrary output variables: data about the primary hit of every pixel, written next to
+// the beauty image for compositing and debugging
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Aov {
+    // Distance from the camera along the viewing direction
+    Depth,
+    // World space surface normal
+    Normal,
+    // Material color, without any lighting
+    Albedo,
+    // World space hit position
+    Position,
+    // Index of the entity in World::entities, plus one
+    EntityId,
+    MaterialId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 6] = [Aov::Depth, Aov::Normal, Aov::Albedo, Aov::Position, Aov::EntityId, Aov::MaterialId];
+
+    // The selection without repeats, in order, so every AOV is written once
+    pub fn unique(aovs: &[Aov]) -> Vec<Aov> {
+        let mut unique = Vec::new();
+        for aov in aovs {
+            if !unique.contains(aov) {
+                unique.push(*aov);
+            }
+        }
+        unique
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::EntityId => "entity_id",
+            Aov::MaterialId => "material_id",
+        }
+    }
+
+    // EXR channel names, following the usual Z / N / P conventions
+    fn channel_names(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal => &["N.X", "N.Y", "N.Z"],
+            Aov::Albedo => &["albedo.R", "albedo.G", "albedo.B"],
+            Aov::Position => &["P.X", "P.Y", "P.Z"],
+            Aov::EntityId => &["entity_id"],
+            Aov::MaterialId => &["material_id"],
+        }
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Aov::ALL.iter()
+            .find(|aov| aov.name() == s)
+            .copied()
+            .ok_or_else(|| format!(
+                "unknown AOV \"{}\", expected depth, normal, albedo, position, entity_id or material_id", s
+            ))
+    }
+}
+
+// AOV values of one camera ray, or of a resolved pixel. Ids are 0 for the sky.
+#[derive(Copy, Clone)]
+pub struct AovSample {
+    // Fraction of the rays that hit geometry, 0 or 1 for a single ray
+    pub coverage: f64,
+    pub depth: f64,
+    pub normal: Vector3<f64>,
+    pub albedo: Vector3<f64>,
+    pub position: Vector3<f64>,
+    pub entity_id: u32,
+    pub material_id: u32,
+}
+
+impl AovSample {
+    pub fn background() -> AovSample {
+        let zero = Vector3 {x: 0., y: 0., z: 0.};
+        AovSample {
+            coverage: 0.,
+            depth: 0.,
+            normal: zero,
+            albedo: zero,
+            position: zero,
+            entity_id: 0,
+            material_id: 0,
+        }
+    }
+
+    pub fn is_background(&self) -> bool {
+        self.coverage <= 0.
+    }
+}
+
+// Averages the continuous AOVs over the samples that hit something. Ids can't be averaged,
+// they come from the sample closest to the pixel center.
+#[derive(Copy, Clone)]
+pub struct AovAccumulator {
+    sum: AovSample,
+    hits: u32,
+    samples: u32,
+    entity_id: u32,
+    material_id: u32,
+    closest: f64,
+}
+
+impl AovAccumulator {
+    pub fn new() -> AovAccumulator {
+        AovAccumulator {
+            sum: AovSample::background(),
+            hits: 0,
+            samples: 0,
+            entity_id: 0,
+            material_id: 0,
+            closest: f64::MAX,
+        }
+    }
+
+    // center_distance is how far from the pixel center the sample was taken
+    pub fn add(&mut self, sample: &AovSample, center_distance: f64) {
+        if center_distance < self.closest {
+            self.closest = center_distance;
+            self.entity_id = sample.entity_id;
+            self.material_id = sample.material_id;
+        }
+        self.samples += 1;
+        if sample.is_background() {
+            return;
+        }
+        self.sum.depth += sample.depth;
+        self.sum.normal += sample.normal;
+        self.sum.albedo += sample.albedo;
+        self.sum.position += sample.position;
+        self.hits += 1;
+    }
+
+    pub fn resolve(&self) -> AovSample {
+        let mut sample = AovSample::background();
+        if self.hits > 0 {
+            let n = self.hits as f64;
+            sample.coverage = n / self.samples as f64;
+            sample.depth = self.sum.depth / n;
+            sample.albedo = self.sum.albedo / n;
+            sample.position = self.sum.position / n;
+            if self.sum.normal.magnitude2() > 0. {
+                sample.normal = self.sum.normal.normalize();
+            }
+        }
+        sample.entity_id = self.entity_id;
+        sample.material_id = self.material_id;
+        sample
+    }
+}
+
+impl Default for AovAccumulator {
+    fn default() -> Self {
+        AovAccumulator::new()
+    }
+}
+
+// Resolved AOVs of a whole image, row by row like the framebuffer
+pub struct AovBuffers {
+    width: u32,
+    height: u32,
+    pixels: Vec<AovSample>,
+}
+
+impl AovBuffers {
+    pub fn new(width: u32, height: u32) -> AovBuffers {
+        AovBuffers {
+            width,
+            height,
+            pixels: vec![AovSample::background(); (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> &AovSample {
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, sample: AovSample) {
+        self.pixels[(y * self.width + x) as usize] = sample;
+    }
+
+    // Raw values, one Vec per channel
+    pub fn channels(&self, aov: Aov) -> Vec<Vec<f32>> {
+        let scalar = |f: &dyn Fn(&AovSample) -> f64| vec![self.pixels.iter().map(|p| f(p) as f32).collect()];
+        let vector = |f: &dyn Fn(&AovSample) -> Vector3<f64>| (0..3)
+            .map(|i| self.pixels.iter().map(|p| f(p)[i] as f32).collect())
+            .collect();
+        match aov {
+            Aov::Depth => scalar(&|p| p.depth),
+            Aov::Normal => vector(&|p| p.normal),
+            Aov::Albedo => vector(&|p| p.albedo),
+            Aov::Position => vector(&|p| p.position),
+            Aov::EntityId => scalar(&|p| p.entity_id as f64),
+            Aov::MaterialId => scalar(&|p| p.material_id as f64),
+        }
+    }
+
+    pub fn exr_channels(&self, aov: Aov) -> Vec<ExrChannel> {
+        aov.channel_names().iter()
+            .zip(self.channels(aov))
+            .map(|(name, samples)| match aov {
+                Aov::Albedo | Aov::Normal => ExrChannel::new(name, samples),
+                _ => ExrChannel::new_full_precision(name, samples),
+            })
+            .collect()
+    }
+
+    // Viewable version of an AOV: depth as gray with near being bright, normals mapped from
+    // [-1, 1] to [0, 1], positions normalized to the bounds of the image, ids as random colors
+    pub fn visualize(&self, aov: Aov) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(self.width, self.height);
+        let hits = || self.pixels.iter().filter(|p| !p.is_background());
+        let max_depth = hits().map(|p| p.depth).fold(0., f64::max);
+        let (min, max) = hits().fold(
+            (Vector3 {x: f64::MAX, y: f64::MAX, z: f64::MAX}, Vector3 {x: f64::MIN, y: f64::MIN, z: f64::MIN}),
+            |(min, max), p| (
+                Vector3 {x: min.x.min(p.position.x), y: min.y.min(p.position.y), z: min.z.min(p.position.z)},
+                Vector3 {x: max.x.max(p.position.x), y: max.y.max(p.position.y), z: max.z.max(p.position.z)},
+            ),
+        );
+
+        for (pixel, out) in self.pixels.iter().zip(framebuffer.pixels_mut()) {
+            if pixel.is_background() {
+                continue;
+            }
+            let color = match aov {
+                Aov::Depth => {
+                    let d = if max_depth > 0. { 1. - pixel.depth / max_depth } else { 0. };
+                    Vector3 {x: d, y: d, z: d}
+                }
+                Aov::Normal => pixel.normal * 0.5 + Vector3 {x: 0.5, y: 0.5, z: 0.5},
+                Aov::Albedo => pixel.albedo,
+                Aov::Position => {
+                    let normalize = |v: f64, lo: f64, hi: f64| if hi > lo { (v - lo) / (hi - lo) } else { 0. };
+                    Vector3 {
+                        x: normalize(pixel.position.x, min.x, max.x),
+                        y: normalize(pixel.position.y, min.y, max.y),
+                        z: normalize(pixel.position.z, min.z, max.z),
+                    }
+                }
+                Aov::EntityId => id_color(pixel.entity_id),
+                Aov::MaterialId => id_color(pixel.material_id),
+            };
+            *out = color.cast().unwrap();
+        }
+        framebuffer
+    }
+}
+
+// Saves the beauty image together with the selected AOVs. EXR files get the AOVs as extra
+// layers with their raw values, other formats get a viewable image per AOV named
+// "<name>.<aov>.<extension>" next to the beauty image.
+pub fn save_with_aovs<P: AsRef<Path>>(
+    path: P,
+    beauty: &Framebuffer,
+    transform: &OutputTransform,
+    aovs: &AovBuffers,
+    selected: &[Aov],
+) -> Result<()> {
+    let path = path.as_ref();
+    let extension = output::extension(path);
+    if extension == "exr" {
+        let mut channels = beauty.exr_channels("");
+        for aov in selected {
+            channels.extend(aovs.exr_channels(*aov));
+        }
+        return output::write_exr(path, (beauty.width(), beauty.height()), channels, transform.exr_precision);
+    }
+
+    beauty.save(path, transform)?;
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    for aov in selected {
+        let aov_path = path.with_file_name(format!("{}.{}.{}", stem, aov.name(), extension));
+        aovs.visualize(*aov).save(aov_path, &OutputTransform::default())?;
+    }
+    Ok(())
+}
+
+// Stable colors with hues spread by the golden ratio, so neighbouring ids are easy to tell
+// apart. Black for 0.
+fn id_color(id: u32) -> Vector3<f64> {
+    if id == 0 {
+        return Vector3 {x: 0., y: 0., z: 0.};
+    }
+    let hue = (id as f64 * 0.618_033_988_749_895).fract() * 6.;
+    let channel = |offset: f64| {
+        let k = (offset + hue) % 6.;
+        0.9 - 0.9 * 0.7 * k.min(4. - k).clamp(0., 1.)
+    };
+    Vector3 {x: channel(5.), y: channel(3.), z: channel(1.)}
+}
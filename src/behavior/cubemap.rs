@@ -2,56 +2,54 @@ extern crate cgmath;
 
 use crate::common::*;
 use crate::tracer::RayTracer;
+use crate::lighting::EnvironmentMap;
+use crate::{status, statusln};
 
+use anyhow::{Context, Result};
 use std::io::Write;
 use cgmath::Vector3;
 
+type LinearImage = image::ImageBuffer<image::Rgb<f32>, std::vec::Vec<f32>>;
+
 pub struct CubemapBehavior {
-    // left, right, front, back, down, up
-    maps: [image::ImageBuffer<image::Rgb<u8>, std::vec::Vec<u8>>; 6],
+    // left, right, down, up, front, back, decoded to linear
+    maps: [LinearImage; 6],
     mix: f64,
 }
 
 impl CubemapBehavior {
-    pub fn new(folder: &str, mix: f64) -> CubemapBehavior {
-        print!("\nLoading assets...");
-        std::io::stdout().flush().unwrap();
-        let left = image::open(format!("{}/left.png", folder))
-            .unwrap()
-            .to_rgb8();
-        print!(".");
-        std::io::stdout().flush().unwrap();
-        let right = image::open(format!("{}/right.png", folder))
-            .unwrap()
-            .to_rgb8();
-        print!(".");
-        std::io::stdout().flush().unwrap();
-        let front = image::open(format!("{}/front.png", folder))
-            .unwrap()
-            .to_rgb8();
-        print!(".");
-        std::io::stdout().flush().unwrap();
-        let back = image::open(format!("{}/back.png", folder))
-            .unwrap()
-            .to_rgb8();
-        print!(".");
+    pub fn new(folder: &str, mix: f64) -> Result<CubemapBehavior> {
+        status!("\nLoading assets...");
         std::io::stdout().flush().unwrap();
-        let up = image::open(format!("{}/up.png", folder)).unwrap().to_rgb8();
-        print!(".");
+        let left = load_face(folder, "left")?;
+        let right = load_face(folder, "right")?;
+        let front = load_face(folder, "front")?;
+        let back = load_face(folder, "back")?;
+        let up = load_face(folder, "up")?;
+        let down = load_face(folder, "down")?;
+        let maps: [LinearImage; 6] = [left, right, down, up, front, back];
+        statusln!(".");
         std::io::stdout().flush().unwrap();
-        let down = image::open(format!("{}/down.png", folder))
-            .unwrap()
-            .to_rgb8();
-        print!(".");
-        std::io::stdout().flush().unwrap();
-        let maps: [image::ImageBuffer<image::Rgb<u8>, std::vec::Vec<u8>>; 6] =
-            [left, right, down, up, front, back];
-        println!(".");
-        std::io::stdout().flush().unwrap();
-        CubemapBehavior { maps, mix }
+        Ok(CubemapBehavior { maps, mix })
     }
 }
 
+// Cubemap faces are sRGB encoded PNGs
+fn load_face(folder: &str, face: &str) -> Result<LinearImage> {
+    let path = format!("{}/{}.png", folder, face);
+    let encoded = image::open(&path)
+        .with_context(|| format!("Could not load cubemap face {}", path))?
+        .to_rgb8();
+    let lookup: Vec<f32> = (0..=255u8).map(|i| rgb_vec(image::Rgb([i, i, i])).x as f32).collect();
+    let decoded = image::ImageBuffer::from_fn(encoded.width(), encoded.height(), |x, y| {
+        let p = encoded.get_pixel(x, y);
+        image::Rgb([lookup[p[0] as usize], lookup[p[1] as usize], lookup[p[2] as usize]])
+    });
+    status!(".");
+    std::io::stdout().flush().unwrap();
+    Ok(decoded)
+}
+
 impl RayBehavior for CubemapBehavior {
     fn compute(
         &self,
@@ -60,7 +58,17 @@ impl RayBehavior for CubemapBehavior {
         _collision: &ColliderResult,
         _tracer: &RayTracer,
     ) -> Option<Vector3<f64>> {
-        let result = cubemap(ray.direction.x, ray.direction.y, ray.direction.z);
+        Some(self.radiance(ray.direction))
+    }
+
+    fn mix(&self) -> f64 {
+        self.mix
+    }
+}
+
+impl EnvironmentMap for CubemapBehavior {
+    fn radiance(&self, direction: Vector3<f64>) -> Vector3<f64> {
+        let result = cubemap(direction.x, direction.y, direction.z);
         let map = &self.maps[result.0 as usize];
         let mut px = (
             (result.1 * map.dimensions().0 as f64) as u32,
@@ -73,11 +81,7 @@ impl RayBehavior for CubemapBehavior {
             px.1 = map.dimensions().1 - 1;
         }
         let sample = map.get_pixel(px.0, px.1);
-        Some(rgb_vec(*sample))
-    }
-
-    fn mix(&self) -> f64 {
-        self.mix
+        Vector3 {x: sample[0] as f64, y: sample[1] as f64, z: sample[2] as f64}
     }
 }
 
@@ -0,0 +1,92 @@
+extern crate cgmath;
+
+use crate::bsdf::{reflect, Bsdf, Frame, GlassBsdf};
+use crate::common::{offset_origin, Ray, RayBehavior, ScatterSample, World, ColliderResult};
+use crate::tracer::RayTracer;
+
+use cgmath::{ElementWise, InnerSpace, Vector3};
+
+// Refracted rays need a few more bounces than mirrors to get through an object and out again
+const MAX_BOUNCES: u32 = 6;
+
+// Rays carrying less than this are not traced any further
+const MIN_WEIGHT: f64 = 1e-3;
+
+// Glass, water and other clear materials. The surface must be closed with its normals
+// pointing outwards, like a sphere or a watertight mesh, for rays to know when they are
+// inside. absorption is the Beer-Lambert coefficient of the inside per unit of distance.
+pub struct DielectricBehavior {
+    bsdf: GlassBsdf,
+    absorption: Vector3<f64>,
+    mix: f64,
+}
+
+impl DielectricBehavior {
+    pub fn new(ior: f64, absorption: Vector3<f64>, mix: f64) -> DielectricBehavior {
+        DielectricBehavior { bsdf: GlassBsdf::new(ior), absorption, mix }
+    }
+}
+
+impl RayBehavior for DielectricBehavior {
+    // Traces both the reflected and the refracted ray, weighted by the Fresnel reflectance
+    fn compute(
+        &self,
+        ray: &Ray,
+        world: &World,
+        collision: &ColliderResult,
+        tracer: &RayTracer,
+    ) -> Option<Vector3<f64>> {
+        if ray.bounce > MAX_BOUNCES {
+            return None;
+        }
+        let frame = Frame::new(collision.normal);
+        let wo = frame.to_local(-ray.direction);
+        let trace = |direction: Vector3<f64>| {
+            let direction = frame.to_world(direction);
+            let next = Ray {
+                origin: offset_origin(collision.position, collision.normal, direction),
+                direction,
+                bounce: ray.bounce + 1,
+            };
+            tracer.cast(&next, world)
+        };
+
+        let reflectance = self.bsdf.reflectance(wo);
+        let mut result = Vector3 {x: 0., y: 0., z: 0.};
+        if reflectance > MIN_WEIGHT {
+            result += trace(reflect(wo)) * reflectance;
+        }
+        if let Some(refracted) = self.bsdf.refract(wo) {
+            if 1. - reflectance > MIN_WEIGHT {
+                result += trace(refracted) * (1. - reflectance);
+            }
+        }
+        if wo.z < 0. {
+            let distance = (collision.position - ray.origin).magnitude();
+            result = result.mul_element_wise(self.transmittance(distance));
+        }
+        Some(result)
+    }
+
+    fn mix(&self) -> f64 {
+        self.mix
+    }
+
+    fn sample(&self, collision: &ColliderResult, wo: Vector3<f64>, u: f64, v: f64) -> Option<ScatterSample> {
+        let frame = Frame::new(collision.normal);
+        let sample = self.bsdf.sample(frame.to_local(wo), u, v)?;
+        Some(ScatterSample {
+            direction: frame.to_world(sample.direction),
+            weight: sample.weight * self.mix,
+            ..sample
+        })
+    }
+
+    fn transmittance(&self, distance: f64) -> Vector3<f64> {
+        Vector3 {
+            x: (-self.absorption.x * distance).exp(),
+            y: (-self.absorption.y * distance).exp(),
+            z: (-self.absorption.z * distance).exp(),
+        }
+    }
+}
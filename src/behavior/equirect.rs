@@ -0,0 +1,45 @@
+extern crate cgmath;
+
+use crate::common::*;
+use crate::texture::ImageTexture;
+use crate::tracer::RayTracer;
+use crate::lighting::{environment::direction_lat_long, EnvironmentMap};
+
+use anyhow::Result;
+use cgmath::{InnerSpace, Vector3};
+use std::path::Path;
+
+// Sky from a single equirectangular (latitude-longitude) panorama, the top row is straight
+// up. Radiance .hdr files are used as is, other formats are taken to be sRGB.
+pub struct EquirectBehavior {
+    image: ImageTexture,
+    mix: f64,
+}
+
+impl EquirectBehavior {
+    pub fn load<P: AsRef<Path>>(path: P, mix: f64) -> Result<EquirectBehavior> {
+        Ok(EquirectBehavior { image: ImageTexture::load(path)?, mix })
+    }
+}
+impl RayBehavior for EquirectBehavior {
+    fn compute(
+        &self,
+        ray: &Ray,
+        _world: &World,
+        _collision: &ColliderResult,
+        _tracer: &RayTracer,
+    ) -> Option<Vector3<f64>> {
+        Some(self.radiance(ray.direction))
+    }
+
+    fn mix(&self) -> f64 {
+        self.mix
+    }
+}
+
+impl EnvironmentMap for EquirectBehavior {
+    fn radiance(&self, direction: Vector3<f64>) -> Vector3<f64> {
+        let (u, v) = direction_lat_long(direction.normalize());
+        self.image.nearest(u, v)
+    }
+}
@@ -1,20 +1,36 @@
 extern crate cgmath;
 
-use crate::common::{Ray, RayBehavior, World, ColliderResult};
+use crate::common::{facing, tangent_frame, Ray, RayBehavior, ScatterSample, World, ColliderResult};
+use crate::sampling;
+use crate::texture::Texture;
 use crate::tracer::RayTracer;
-use crate::lighting::LightRay;
+use crate::lighting::sample_lights;
 
-use cgmath::{Vector3, InnerSpace};
+use cgmath::{ElementWise, InnerSpace, Vector3};
+use std::sync::Arc;
 
 pub struct LambertBehavior {
     albedo: f64,
     mix: f64,
     color: Vector3<f64>,
+    texture: Option<Arc<dyn Texture>>,
 }
 
 impl LambertBehavior {
     pub fn new(albedo: f64, mix: f64, color: Vector3<f64>) -> LambertBehavior {
-        LambertBehavior { albedo, mix, color }
+        LambertBehavior { albedo, mix, color, texture: None }
+    }
+
+    // Multiplies the color
+    pub fn set_texture(&mut self, texture: Arc<dyn Texture>) {
+        self.texture = Some(texture);
+    }
+
+    fn color(&self, collision: &ColliderResult) -> Vector3<f64> {
+        match &self.texture {
+            Some(texture) => self.color.mul_element_wise(texture.color(collision)),
+            None => self.color,
+        }
     }
 }
 
@@ -31,20 +47,42 @@ impl RayBehavior for LambertBehavior {
             y: 0.,
             z: 0.,
         };
-        for light_source in world.light_sources.iter() {
-            if light_source.visible(collision.position, collision.normal, world) {
-                let LightRay { power, direction } =
-                    light_source.illuminate(collision.position, collision.normal);
-                let power =
-                    power * (self.albedo / std::f64::consts::PI) * -collision.normal.dot(direction);
-                let power = power.max(0.);
-                result += self.color * power;
-            }
-        }
+        let color = self.color(collision);
+        sample_lights(world, collision.position, collision.normal, |sample, weight| {
+            let factor = weight * (self.albedo / std::f64::consts::PI) * -collision.normal.dot(sample.direction);
+            result += color.mul_element_wise(sample.radiance) * factor.max(0.);
+        });
         Some(result)
     }
 
     fn mix(&self) -> f64 {
         self.mix
     }
+
+    fn evaluate(&self, collision: &ColliderResult, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+        if facing(collision.normal, wo).dot(wi) <= 0. {
+            return Vector3 {x: 0., y: 0., z: 0.};
+        }
+        self.color(collision) * (self.mix * self.albedo / std::f64::consts::PI)
+    }
+
+    fn sample(&self, collision: &ColliderResult, wo: Vector3<f64>, u: f64, v: f64) -> Option<ScatterSample> {
+        let normal = facing(collision.normal, wo);
+        let (tangent, bitangent) = tangent_frame(normal);
+        let (local, cos) = sampling::cosine_hemisphere(u, v);
+        if cos <= 0. {
+            return None;
+        }
+        Some(ScatterSample {
+            direction: tangent * local.x + bitangent * local.y + normal * local.z,
+            // The cosine and 1 / π cancel out against the density
+            weight: self.color(collision) * (self.mix * self.albedo),
+            pdf: cos / std::f64::consts::PI,
+            specular: false,
+        })
+    }
+
+    fn pdf(&self, collision: &ColliderResult, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        facing(collision.normal, wo).dot(wi).max(0.) / std::f64::consts::PI
+    }
 }
\ No newline at end of file
@@ -1,4 +1,7 @@
 pub mod cubemap;
+pub mod dielectric;
+pub mod equirect;
 pub mod lambert;
 pub mod phong;
+pub mod principled;
 pub mod reflection;
\ No newline at end of file
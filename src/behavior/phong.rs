@@ -1,8 +1,8 @@
 extern crate cgmath;
 
-use crate::common::{Ray, RayBehavior, World, ColliderResult};
+use crate::common::{facing, Ray, RayBehavior, World, ColliderResult};
 use crate::tracer::RayTracer;
-use crate::lighting::LightRay;
+use crate::lighting::sample_lights;
 
 use cgmath::{Vector3, InnerSpace};
 
@@ -34,15 +34,23 @@ impl RayBehavior for PhongBehavior {
             y: 0.,
             z: 0.,
         };
-        for light_source in world.light_sources.iter() {
-            if light_source.visible(collision.position, collision.normal, world) {
-                let LightRay { power, direction } =
-                    light_source.illuminate(collision.position, collision.normal);
-                let ray_bisector = (-direction - ray.direction).normalize();
-                let power = power * ray_bisector.dot(collision.normal).max(0.).powi(self.alpha);
-                result += light_source.color() * power;
-            }
-        }
+        sample_lights(world, collision.position, collision.normal, |sample, weight| {
+            let ray_bisector = (-sample.direction - ray.direction).normalize();
+            result += sample.radiance * weight * ray_bisector.dot(collision.normal).max(0.).powi(self.alpha);
+        });
         Some(result)
     }
+
+    // Only lit by light sampling, the lobe is never sampled. Divided by the cosine the caller
+    // multiplies with, so direct light matches the Whitted result.
+    fn evaluate(&self, collision: &ColliderResult, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+        let normal = facing(collision.normal, wo);
+        let cos = normal.dot(wi);
+        if cos <= 0. {
+            return Vector3 {x: 0., y: 0., z: 0.};
+        }
+        let ray_bisector = (wi + wo).normalize();
+        let value = self.mix * ray_bisector.dot(normal).max(0.).powi(self.alpha) / cos;
+        Vector3 {x: value, y: value, z: value}
+    }
 }
@@ -0,0 +1,133 @@
+extern crate cgmath;
+
+use crate::bsdf::{Bsdf, Frame, PrincipledBsdf};
+use crate::common::{facing, offset_origin, Ray, RayBehavior, ScatterSample, World, ColliderResult};
+use crate::texture::Texture;
+use crate::tracer::RayTracer;
+use crate::lighting::sample_light;
+
+use cgmath::{ElementWise, InnerSpace, Vector3};
+use std::sync::Arc;
+
+// Physically based material, see PrincipledBsdf
+pub struct PrincipledBehavior {
+    bsdf: PrincipledBsdf,
+    base_color: Vector3<f64>,
+    texture: Option<Arc<dyn Texture>>,
+    metallic_texture: Option<Arc<dyn Texture>>,
+    roughness_texture: Option<Arc<dyn Texture>>,
+    mix: f64,
+}
+
+impl PrincipledBehavior {
+    pub fn new(bsdf: PrincipledBsdf, base_color: Vector3<f64>, mix: f64) -> PrincipledBehavior {
+        PrincipledBehavior { bsdf, base_color, texture: None, metallic_texture: None, roughness_texture: None, mix }
+    }
+
+    pub fn base_color(&self) -> Vector3<f64> {
+        self.base_color
+    }
+
+    pub fn texture(&self) -> Option<Arc<dyn Texture>> {
+        self.texture.clone()
+    }
+
+    // Multiplies the base color
+    pub fn set_texture(&mut self, texture: Arc<dyn Texture>) {
+        self.texture = Some(texture);
+    }
+
+    // The values of these textures multiply the metallic and roughness of the BSDF
+    pub fn set_metallic_texture(&mut self, texture: Arc<dyn Texture>) {
+        self.metallic_texture = Some(texture);
+    }
+
+    pub fn set_roughness_texture(&mut self, texture: Arc<dyn Texture>) {
+        self.roughness_texture = Some(texture);
+    }
+
+    // The BSDF with the base color, metallic and roughness at the hit
+    fn bsdf(&self, collision: &ColliderResult) -> PrincipledBsdf {
+        let mut bsdf = self.bsdf;
+        if let Some(texture) = &self.texture {
+            bsdf.set_base_color(self.base_color.mul_element_wise(texture.color(collision)));
+        }
+        if let Some(texture) = &self.metallic_texture {
+            bsdf.set_metallic(self.bsdf.metallic() * texture.value(collision));
+        }
+        if let Some(texture) = &self.roughness_texture {
+            bsdf.set_roughness(self.bsdf.roughness() * texture.value(collision));
+        }
+        bsdf
+    }
+
+    // Shading frame on the side of the surface that wo leaves from
+    fn frame(collision: &ColliderResult, wo: Vector3<f64>) -> Frame {
+        Frame::new(facing(collision.normal, wo))
+    }
+}
+
+impl RayBehavior for PrincipledBehavior {
+    // Direct light through the BSDF, plus a mirror ray for what the specular lobes reflect.
+    // Rough reflections only blur in the path tracer.
+    fn compute(
+        &self,
+        ray: &Ray,
+        world: &World,
+        collision: &ColliderResult,
+        tracer: &RayTracer,
+    ) -> Option<Vector3<f64>> {
+        let wo = -ray.direction;
+        let frame = PrincipledBehavior::frame(collision, wo);
+        let local_wo = frame.to_local(wo);
+        let bsdf = self.bsdf(collision);
+        let mirror = ray.bounce <= 2;
+        let direction = (ray.direction + frame.normal * (2. * local_wo.z)).normalize();
+        let mut result = Vector3 {x: 0., y: 0., z: 0.};
+        for light_source in world.light_sources.iter() {
+            // The mirror ray sees lights like the environment too, so it takes their mirrored
+            // share of the specular reflection
+            let mirrored = mirror && light_source.emitted(direction).is_some();
+            sample_light(world, light_source.as_ref(), collision.position, frame.normal, |sample, weight| {
+                let wi = frame.to_local(-sample.direction);
+                let f = if mirrored { bsdf.evaluate_unmirrored(local_wo, wi) } else { bsdf.evaluate(local_wo, wi) };
+                result += f.mul_element_wise(sample.radiance) * (weight * wi.z.max(0.));
+            });
+        }
+
+        if mirror {
+            let reflected = Ray {
+                origin: offset_origin(collision.position, frame.normal, direction),
+                direction,
+                bounce: ray.bounce + 1,
+            };
+            let reflectance = bsdf.mirror_reflectance(local_wo.z);
+            result += reflectance.mul_element_wise(tracer.cast(&reflected, world));
+        }
+        Some(result)
+    }
+
+    fn mix(&self) -> f64 {
+        self.mix
+    }
+
+    fn evaluate(&self, collision: &ColliderResult, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+        let frame = PrincipledBehavior::frame(collision, wo);
+        self.bsdf(collision).evaluate(frame.to_local(wo), frame.to_local(wi)) * self.mix
+    }
+
+    fn sample(&self, collision: &ColliderResult, wo: Vector3<f64>, u: f64, v: f64) -> Option<ScatterSample> {
+        let frame = PrincipledBehavior::frame(collision, wo);
+        let sample = self.bsdf(collision).sample(frame.to_local(wo), u, v)?;
+        Some(ScatterSample {
+            direction: frame.to_world(sample.direction),
+            weight: sample.weight * self.mix,
+            ..sample
+        })
+    }
+
+    fn pdf(&self, collision: &ColliderResult, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        let frame = PrincipledBehavior::frame(collision, wo);
+        self.bsdf(collision).pdf(frame.to_local(wo), frame.to_local(wi))
+    }
+}
@@ -1,17 +1,36 @@
 extern crate cgmath;
 
-use crate::common::{Ray, RayBehavior, World, ColliderResult};
+use crate::bsdf::{Bsdf, ConductorLobe, Frame};
+use crate::common::{facing, offset_origin, Ray, RayBehavior, ScatterSample, World, ColliderResult};
+use crate::sampling;
 use crate::tracer::RayTracer;
 
-use cgmath::{Vector3, InnerSpace};
+use cgmath::{ElementWise, Vector3, InnerSpace};
 
+// Glossy rays from the camera take this many samples by default, deeper bounces take one
+pub const DEFAULT_GLOSSY_SAMPLES: u32 = 8;
+
+// Mirror reflection, blurred by roughness into a glossy GGX lobe around the mirror direction
 pub struct ReflectionBehavior {
     mix: f64,
+    lobe: ConductorLobe,
+    samples: u32,
 }
 
 impl ReflectionBehavior {
     pub fn new(mix: f64) -> ReflectionBehavior {
-        ReflectionBehavior { mix }
+        ReflectionBehavior::new_glossy(mix, 0., 1)
+    }
+
+    // roughness in [0, 1], 0 being a perfect mirror. Whitted shading averages samples
+    // reflected rays on camera ray hits.
+    pub fn new_glossy(mix: f64, roughness: f64, samples: u32) -> ReflectionBehavior {
+        let white = Vector3 {x: 1., y: 1., z: 1.};
+        ReflectionBehavior {
+            mix,
+            lobe: ConductorLobe::new(white, roughness),
+            samples: samples.max(1),
+        }
     }
 }
 
@@ -26,19 +45,61 @@ impl RayBehavior for ReflectionBehavior {
         if ray.bounce > 2 {
             return None;
         };
-        let reflected = Ray {
-            origin: collision.position + collision.normal * 0.3,
-            direction: InnerSpace::normalize(reflect(ray.direction, collision.normal)),
-            bounce: ray.bounce + 1,
-        };
-        Some(tracer.cast(&reflected, world))
+        if self.lobe.distribution.is_smooth() {
+            let direction = InnerSpace::normalize(reflect(ray.direction, collision.normal));
+            let reflected = Ray {
+                origin: offset_origin(collision.position, collision.normal, direction),
+                direction,
+                bounce: ray.bounce + 1,
+            };
+            return Some(tracer.cast(&reflected, world));
+        }
+
+        // Nested glossy reflections would multiply the rays, so only camera rays get several
+        let frame = Frame::new(facing(collision.normal, -ray.direction));
+        let wo = frame.to_local(-ray.direction);
+        let count = if ray.bounce == 0 { self.samples } else { 1 };
+        let mut result = Vector3 {x: 0., y: 0., z: 0.};
+        for i in 0..count {
+            let (u, v) = sampling::stratified(i, count);
+            if let Some(sample) = self.lobe.sample(wo, u, v) {
+                let direction = frame.to_world(sample.direction);
+                let reflected = Ray {
+                    origin: offset_origin(collision.position, frame.normal, direction),
+                    direction,
+                    bounce: ray.bounce + 1,
+                };
+                result += sample.weight.mul_element_wise(tracer.cast(&reflected, world));
+            }
+        }
+        Some(result / count as f64)
     }
 
     fn mix(&self) -> f64 {
         self.mix
     }
+
+    fn evaluate(&self, collision: &ColliderResult, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+        let frame = Frame::new(facing(collision.normal, wo));
+        self.lobe.evaluate(frame.to_local(wo), frame.to_local(wi)) * self.mix
+    }
+
+    fn sample(&self, collision: &ColliderResult, wo: Vector3<f64>, u: f64, v: f64) -> Option<ScatterSample> {
+        let frame = Frame::new(facing(collision.normal, wo));
+        let sample = self.lobe.sample(frame.to_local(wo), u, v)?;
+        Some(ScatterSample {
+            direction: frame.to_world(sample.direction),
+            weight: sample.weight * self.mix,
+            ..sample
+        })
+    }
+
+    fn pdf(&self, collision: &ColliderResult, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        let frame = Frame::new(facing(collision.normal, wo));
+        self.lobe.pdf(frame.to_local(wo), frame.to_local(wi))
+    }
 }
 
 fn reflect(d: Vector3<f64>, n: Vector3<f64>) -> Vector3<f64> {
     d - (n * (n.dot(d)) * 2.0)
-}
\ No newline at end of file
+}
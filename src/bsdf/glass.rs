@@ -0,0 +1,69 @@
+extern crate cgmath;
+
+use crate::bsdf::{fresnel_dielectric, reflect, Bsdf};
+use crate::common::ScatterSample;
+
+use cgmath::Vector3;
+
+// Smooth interface between the outside and a dielectric like glass or water, reflecting and
+// transmitting by the exact Fresnel equations. Unlike the other lobes the frame's normal
+// points outwards rather than towards wo, so wo.z < 0 means the ray arrived from inside.
+pub struct GlassBsdf {
+    pub ior: f64,
+}
+
+impl GlassBsdf {
+    pub fn new(ior: f64) -> GlassBsdf {
+        GlassBsdf { ior }
+    }
+
+    // Fraction of the light reflected for wo, the rest goes through unless it is totally
+    // internally reflected
+    pub fn reflectance(&self, wo: Vector3<f64>) -> f64 {
+        fresnel_dielectric(wo.z, self.ior)
+    }
+
+    // Direction the light seen along wo comes through the interface from, None under total
+    // internal reflection
+    pub fn refract(&self, wo: Vector3<f64>) -> Option<Vector3<f64>> {
+        // Relative index of the side wi is on over the side of wo
+        let (eta, sign) = if wo.z > 0. { (self.ior, 1.) } else { (1. / self.ior, -1.) };
+        let cos_o = wo.z.abs();
+        let sin2_i = (1. - cos_o * cos_o).max(0.) / (eta * eta);
+        if sin2_i >= 1. {
+            return None;
+        }
+        let cos_i = (1. - sin2_i).sqrt();
+        let n = Vector3 {x: 0., y: 0., z: sign};
+        Some(-wo / eta + n * (cos_o / eta - cos_i))
+    }
+}
+
+impl Bsdf for GlassBsdf {
+    // Both directions are deltas, nothing to evaluate
+    fn evaluate(&self, _wo: Vector3<f64>, _wi: Vector3<f64>) -> Vector3<f64> {
+        Vector3 {x: 0., y: 0., z: 0.}
+    }
+
+    // Reflects with the Fresnel reflectance as probability, so the weight is always 1. The
+    // change in radiance across the interface cancels out once the path leaves the glass
+    // again, and is left out.
+    fn sample(&self, wo: Vector3<f64>, u: f64, _v: f64) -> Option<ScatterSample> {
+        let white = Vector3 {x: 1., y: 1., z: 1.};
+        let reflectance = self.reflectance(wo);
+        let direction = match self.refract(wo) {
+            Some(refracted) if u >= reflectance => refracted,
+            _ => reflect(wo),
+        };
+        Some(ScatterSample {
+            direction,
+            weight: white,
+            pdf: 1.,
+            specular: true,
+        })
+    }
+
+    fn pdf(&self, _wo: Vector3<f64>, _wi: Vector3<f64>) -> f64 {
+        0.
+    }
+}
@@ -0,0 +1,46 @@
+extern crate cgmath;
+
+use crate::bsdf::Bsdf;
+use crate::common::ScatterSample;
+use crate::sampling;
+
+use cgmath::Vector3;
+use std::f64::consts::PI;
+
+// Ideal diffuse reflection
+#[derive(Copy, Clone)]
+pub struct LambertianLobe {
+    pub color: Vector3<f64>,
+}
+
+impl LambertianLobe {
+    pub fn new(color: Vector3<f64>) -> LambertianLobe {
+        LambertianLobe { color }
+    }
+}
+
+impl Bsdf for LambertianLobe {
+    fn evaluate(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+        if wo.z <= 0. || wi.z <= 0. {
+            return Vector3 {x: 0., y: 0., z: 0.};
+        }
+        self.color / PI
+    }
+
+    fn sample(&self, wo: Vector3<f64>, u: f64, v: f64) -> Option<ScatterSample> {
+        let (direction, cos) = sampling::cosine_hemisphere(u, v);
+        if wo.z <= 0. || cos <= 0. {
+            return None;
+        }
+        Some(ScatterSample {
+            direction,
+            weight: self.color,
+            pdf: cos / PI,
+            specular: false,
+        })
+    }
+
+    fn pdf(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        if wo.z <= 0. || wi.z <= 0. { 0. } else { wi.z / PI }
+    }
+}
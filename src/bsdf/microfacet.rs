@@ -0,0 +1,209 @@
+extern crate cgmath;
+
+use crate::bsdf::{fresnel_dielectric, fresnel_schlick, reflect, Bsdf};
+use crate::common::ScatterSample;
+
+use cgmath::{InnerSpace, Vector3};
+use std::f64::consts::PI;
+
+// Below this alpha a surface is treated as a perfect mirror, the distribution gets too peaked
+// to evaluate
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals, with Smith's height correlated
+// shadowing and sampling of the normals visible from wo (Heitz 2018)
+#[derive(Copy, Clone, Debug)]
+pub struct GgxDistribution {
+    pub alpha: f64,
+}
+
+impl GgxDistribution {
+    // Perceptually linear roughness in [0, 1], alpha is its square
+    pub fn from_roughness(roughness: f64) -> GgxDistribution {
+        let roughness = roughness.clamp(0., 1.);
+        GgxDistribution { alpha: roughness * roughness }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < SMOOTH_ALPHA
+    }
+
+    // Density of microfacet normals h
+    pub fn d(&self, h: Vector3<f64>) -> f64 {
+        if h.z <= 0. {
+            return 0.;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = h.z * h.z * (a2 - 1.) + 1.;
+        a2 / (PI * t * t)
+    }
+
+    fn lambda(&self, w: Vector3<f64>) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0. {
+            return f64::INFINITY;
+        }
+        let tan2 = (1. - cos2).max(0.) / cos2;
+        ((1. + self.alpha * self.alpha * tan2).sqrt() - 1.) / 2.
+    }
+
+    // Fraction of the microfacets visible from w
+    pub fn g1(&self, w: Vector3<f64>) -> f64 {
+        1. / (1. + self.lambda(w))
+    }
+
+    // Fraction of the microfacets visible from both wo and wi
+    pub fn g(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Microfacet normal visible from wo, which must be above the surface
+    pub fn sample_visible(&self, wo: Vector3<f64>, u: f64, v: f64) -> Vector3<f64> {
+        // Stretch to a hemisphere configuration
+        let vh = Vector3 {x: self.alpha * wo.x, y: self.alpha * wo.y, z: wo.z}.normalize();
+        let length2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length2 > 0. {
+            Vector3 {x: -vh.y, y: vh.x, z: 0.} / length2.sqrt()
+        } else {
+            Vector3 {x: 1., y: 0., z: 0.}
+        };
+        let t2 = vh.cross(t1);
+
+        // Point on the projected area of the visible hemisphere
+        let r = u.sqrt();
+        let phi = 2. * PI * v;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1. + vh.z);
+        let p2 = (1. - s) * (1. - p1 * p1).max(0.).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1. - p1 * p1 - p2 * p2).max(0.).sqrt();
+
+        // Back to the ellipsoid configuration
+        Vector3 {x: self.alpha * nh.x, y: self.alpha * nh.y, z: nh.z.max(0.)}.normalize()
+    }
+
+    // Density of sample_visible picking h
+    pub fn pdf_visible(&self, wo: Vector3<f64>, h: Vector3<f64>) -> f64 {
+        if wo.z <= 0. {
+            return 0.;
+        }
+        self.g1(wo) * wo.dot(h).max(0.) * self.d(h) / wo.z
+    }
+}
+
+// Reflection off a rough conductor, tinted by the metal's color at normal incidence
+#[derive(Copy, Clone)]
+pub struct ConductorLobe {
+    pub f0: Vector3<f64>,
+    pub distribution: GgxDistribution,
+}
+
+impl ConductorLobe {
+    pub fn new(f0: Vector3<f64>, roughness: f64) -> ConductorLobe {
+        ConductorLobe { f0, distribution: GgxDistribution::from_roughness(roughness) }
+    }
+}
+
+impl Bsdf for ConductorLobe {
+    fn evaluate(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+        evaluate_reflection(&self.distribution, wo, wi, |cos| fresnel_schlick(self.f0, cos))
+    }
+
+    fn sample(&self, wo: Vector3<f64>, u: f64, v: f64) -> Option<ScatterSample> {
+        sample_reflection(&self.distribution, wo, u, v, |cos| fresnel_schlick(self.f0, cos))
+    }
+
+    fn pdf(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        pdf_reflection(&self.distribution, wo, wi)
+    }
+}
+
+// Reflection off a rough dielectric interface with index of refraction ior. Only the reflected
+// part, the light going through is left to whatever lies below, like the diffuse base of
+// PrincipledBsdf. scale adjusts the strength of the reflection, 1 is physically correct.
+#[derive(Copy, Clone)]
+pub struct DielectricLobe {
+    pub ior: f64,
+    pub scale: f64,
+    pub distribution: GgxDistribution,
+}
+
+impl DielectricLobe {
+    pub fn new(ior: f64, scale: f64, roughness: f64) -> DielectricLobe {
+        DielectricLobe { ior, scale, distribution: GgxDistribution::from_roughness(roughness) }
+    }
+
+    pub fn reflectance(&self, cos: f64) -> f64 {
+        self.scale * fresnel_dielectric(cos, self.ior)
+    }
+
+    fn fresnel(&self, cos: f64) -> Vector3<f64> {
+        let f = self.reflectance(cos);
+        Vector3 {x: f, y: f, z: f}
+    }
+}
+
+impl Bsdf for DielectricLobe {
+    fn evaluate(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+        evaluate_reflection(&self.distribution, wo, wi, |cos| self.fresnel(cos))
+    }
+
+    fn sample(&self, wo: Vector3<f64>, u: f64, v: f64) -> Option<ScatterSample> {
+        sample_reflection(&self.distribution, wo, u, v, |cos| self.fresnel(cos))
+    }
+
+    fn pdf(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        pdf_reflection(&self.distribution, wo, wi)
+    }
+}
+
+// Torrance-Sparrow reflection with the Fresnel term as a function of the cosine between
+// the direction and the microfacet normal
+
+fn evaluate_reflection<F>(distribution: &GgxDistribution, wo: Vector3<f64>, wi: Vector3<f64>, fresnel: F) -> Vector3<f64>
+where
+    F: Fn(f64) -> Vector3<f64>,
+{
+    if distribution.is_smooth() || wo.z <= 0. || wi.z <= 0. {
+        return Vector3 {x: 0., y: 0., z: 0.};
+    }
+    let h = (wo + wi).normalize();
+    fresnel(wo.dot(h)) * (distribution.d(h) * distribution.g(wo, wi) / (4. * wo.z * wi.z))
+}
+
+fn sample_reflection<F>(distribution: &GgxDistribution, wo: Vector3<f64>, u: f64, v: f64, fresnel: F) -> Option<ScatterSample>
+where
+    F: Fn(f64) -> Vector3<f64>,
+{
+    if wo.z <= 0. {
+        return None;
+    }
+    if distribution.is_smooth() {
+        return Some(ScatterSample {
+            direction: reflect(wo),
+            weight: fresnel(wo.z),
+            pdf: 1.,
+            specular: true,
+        });
+    }
+    let h = distribution.sample_visible(wo, u, v);
+    let cos = wo.dot(h);
+    let wi = h * (2. * cos) - wo;
+    if wi.z <= 0. || cos <= 0. {
+        return None;
+    }
+    Some(ScatterSample {
+        direction: wi,
+        // D and most of the shadowing cancel out against the density
+        weight: fresnel(cos) * (distribution.g(wo, wi) / distribution.g1(wo)),
+        pdf: distribution.pdf_visible(wo, h) / (4. * cos),
+        specular: false,
+    })
+}
+
+fn pdf_reflection(distribution: &GgxDistribution, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+    if distribution.is_smooth() || wo.z <= 0. || wi.z <= 0. {
+        return 0.;
+    }
+    let h = (wo + wi).normalize();
+    distribution.pdf_visible(wo, h) / (4. * wo.dot(h))
+}
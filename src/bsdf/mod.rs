@@ -0,0 +1,83 @@
+extern crate cgmath;
+
+pub mod glass;
+pub mod lambertian;
+pub mod microfacet;
+pub mod principled;
+
+pub use self::glass::GlassBsdf;
+pub use self::lambertian::LambertianLobe;
+pub use self::microfacet::{ConductorLobe, DielectricLobe, GgxDistribution};
+pub use self::principled::PrincipledBsdf;
+
+use crate::common::{tangent_frame, ScatterSample};
+
+use cgmath::{ElementWise, InnerSpace, Vector3};
+
+// A scattering function in the local shading frame, where the normal is +Z. Like the path
+// tracing methods of RayBehavior, wo points back along the incoming ray and wi to where light
+// arrives from, both away from the surface.
+pub trait Bsdf: Sync + Send {
+    // Fraction of the light arriving from wi that leaves towards wo, before the cosine term
+    fn evaluate(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64>;
+
+    // Picks wi for wo, with a local direction in the returned sample
+    fn sample(&self, wo: Vector3<f64>, u: f64, v: f64) -> Option<ScatterSample>;
+
+    // Density of sample picking wi, zero for mirror-like lobes
+    fn pdf(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> f64;
+}
+
+// Orthonormal basis around a shading normal, for moving directions in and out of the
+// local frame of a Bsdf
+#[derive(Copy, Clone)]
+pub struct Frame {
+    pub tangent: Vector3<f64>,
+    pub bitangent: Vector3<f64>,
+    pub normal: Vector3<f64>,
+}
+
+impl Frame {
+    pub fn new(normal: Vector3<f64>) -> Frame {
+        let (tangent, bitangent) = tangent_frame(normal);
+        Frame { tangent, bitangent, normal }
+    }
+
+    pub fn to_local(&self, v: Vector3<f64>) -> Vector3<f64> {
+        Vector3 {x: v.dot(self.tangent), y: v.dot(self.bitangent), z: v.dot(self.normal)}
+    }
+
+    pub fn to_world(&self, v: Vector3<f64>) -> Vector3<f64> {
+        self.tangent * v.x + self.bitangent * v.y + self.normal * v.z
+    }
+}
+
+// Mirror direction of a local direction
+pub fn reflect(w: Vector3<f64>) -> Vector3<f64> {
+    Vector3 {x: -w.x, y: -w.y, z: w.z}
+}
+
+// Unpolarized reflectance of a dielectric interface with relative index of refraction eta
+// (inside over outside), for light arriving at cos_i. A negative cos_i arrives from inside.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_i < 0. { (-cos_i, 1. / eta) } else { (cos_i, eta) };
+    let sin2_t = (1. - cos_i * cos_i).max(0.) / (eta * eta);
+    if sin2_t >= 1. {
+        // Total internal reflection
+        return 1.;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    let r_s = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let r_p = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    (r_s * r_s + r_p * r_p) / 2.
+}
+
+// Schlick's approximation, for metals described by their color at normal incidence
+pub fn fresnel_schlick(f0: Vector3<f64>, cos: f64) -> Vector3<f64> {
+    let white = Vector3 {x: 1., y: 1., z: 1.};
+    f0 + (white - f0).mul_element_wise((1. - cos.clamp(0., 1.)).powi(5))
+}
+
+pub fn luminance(c: Vector3<f64>) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
@@ -0,0 +1,165 @@
+extern crate cgmath;
+
+use crate::bsdf::{fresnel_schlick, luminance, Bsdf, ConductorLobe, DielectricLobe, GgxDistribution, LambertianLobe};
+use crate::common::ScatterSample;
+
+use cgmath::Vector3;
+
+// Material in the style of Disney's principled BSDF: a diffuse base under a dielectric
+// coat, blended into a metal by metallic. The base only gets the light the coat lets through
+// on the way in and out, so the lobes together never reflect more than arrives.
+#[derive(Copy, Clone)]
+pub struct PrincipledBsdf {
+    diffuse: LambertianLobe,
+    dielectric: DielectricLobe,
+    conductor: ConductorLobe,
+    metallic: f64,
+    roughness: f64,
+}
+
+impl PrincipledBsdf {
+    // metallic and roughness in [0, 1], specular scales the reflection of the coat (1 is
+    // physically correct for the ior). Metals reflect their base color at normal incidence.
+    pub fn new(base_color: Vector3<f64>, metallic: f64, roughness: f64, specular: f64, ior: f64) -> PrincipledBsdf {
+        PrincipledBsdf {
+            diffuse: LambertianLobe::new(base_color),
+            dielectric: DielectricLobe::new(ior, specular.max(0.), roughness),
+            conductor: ConductorLobe::new(base_color, roughness),
+            metallic: metallic.clamp(0., 1.),
+            roughness: roughness.clamp(0., 1.),
+        }
+    }
+
+    // For base colors varying over the surface, like textures
+    pub fn set_base_color(&mut self, base_color: Vector3<f64>) {
+        self.diffuse.color = base_color;
+        self.conductor.f0 = base_color;
+    }
+
+    pub fn metallic(&self) -> f64 {
+        self.metallic
+    }
+
+    pub fn set_metallic(&mut self, metallic: f64) {
+        self.metallic = metallic.clamp(0., 1.);
+    }
+
+    pub fn roughness(&self) -> f64 {
+        self.roughness
+    }
+
+    pub fn set_roughness(&mut self, roughness: f64) {
+        let distribution = GgxDistribution::from_roughness(roughness);
+        self.dielectric.distribution = distribution;
+        self.conductor.distribution = distribution;
+        self.roughness = roughness.clamp(0., 1.);
+    }
+
+    // Fraction of the light mirrored at the cosine cos, for the mirror rays of Whitted shading.
+    // A single ray can't blur, so rough surfaces fade the mirror image out instead.
+    pub fn mirror_reflectance(&self, cos: f64) -> Vector3<f64> {
+        let coat = self.coat(cos) * (1. - self.metallic);
+        (fresnel_schlick(self.conductor.f0, cos) * self.metallic + Vector3 {x: coat, y: coat, z: coat}) * self.smoothness()
+    }
+
+    // evaluate without the share of the specular lobes that the mirror ray of Whitted shading
+    // already picks up, for lights that ray can see (the environment)
+    pub fn evaluate_unmirrored(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+        self.diffuse_term(wo, wi) + self.specular_term(wo, wi) * (1. - self.smoothness())
+    }
+
+    // How much of the specular reflection mirror_reflectance keeps
+    fn smoothness(&self) -> f64 {
+        (1. - self.roughness).powi(2)
+    }
+
+    fn diffuse_term(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+        let dielectric = 1. - self.metallic;
+        self.diffuse.evaluate(wo, wi) * (dielectric * (1. - self.coat(wo.z)) * (1. - self.coat(wi.z)))
+    }
+
+    fn specular_term(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+        self.dielectric.evaluate(wo, wi) * (1. - self.metallic) + self.conductor.evaluate(wo, wi) * self.metallic
+    }
+
+    fn coat(&self, cos: f64) -> f64 {
+        self.dielectric.reflectance(cos).min(1.)
+    }
+
+    // Chances of sampling the diffuse, dielectric and conductor lobes, roughly following how
+    // much each of them reflects
+    fn lobe_probabilities(&self, wo: Vector3<f64>) -> [f64; 3] {
+        let dielectric = 1. - self.metallic;
+        let coat = self.coat(wo.z);
+        let weights = [
+            dielectric * (1. - coat) * luminance(self.diffuse.color),
+            // The coat reflects little head on, but its highlights still need some samples
+            dielectric * coat.max(0.1 * self.dielectric.scale.min(1.)),
+            self.metallic * luminance(fresnel_schlick(self.conductor.f0, wo.z)),
+        ];
+        let total: f64 = weights.iter().sum();
+        if total <= 0. {
+            return [0.; 3];
+        }
+        weights.map(|w| w / total)
+    }
+}
+
+impl Bsdf for PrincipledBsdf {
+    fn evaluate(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+        self.diffuse_term(wo, wi) + self.specular_term(wo, wi)
+    }
+
+    fn sample(&self, wo: Vector3<f64>, u: f64, v: f64) -> Option<ScatterSample> {
+        if wo.z <= 0. {
+            return None;
+        }
+        let probabilities = self.lobe_probabilities(wo);
+        let lobes: [(&dyn Bsdf, f64); 3] = [
+            (&self.diffuse, 1.),
+            (&self.dielectric, 1. - self.metallic),
+            (&self.conductor, self.metallic),
+        ];
+
+        // Pick a lobe with u and reuse what is left of it for the lobe's own sample
+        let mut index = probabilities.iter().rposition(|&p| p > 0.)?;
+        let mut end = 0.;
+        for (i, &p) in probabilities.iter().enumerate() {
+            end += p;
+            if p > 0. && u < end {
+                index = i;
+                break;
+            }
+        }
+        let start: f64 = probabilities[..index].iter().sum();
+        let u = ((u - start) / probabilities[index]).clamp(0., 1. - f64::EPSILON);
+        let (lobe, scale) = lobes[index];
+        let sample = lobe.sample(wo, u, v)?;
+
+        if sample.specular {
+            return Some(ScatterSample {
+                weight: sample.weight * (scale / probabilities[index]),
+                ..sample
+            });
+        }
+        // Every rough lobe could have picked the direction
+        let wi = sample.direction;
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0. {
+            return None;
+        }
+        Some(ScatterSample {
+            direction: wi,
+            weight: self.evaluate(wo, wi) * (wi.z / pdf),
+            pdf,
+            specular: false,
+        })
+    }
+
+    fn pdf(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        let probabilities = self.lobe_probabilities(wo);
+        probabilities[0] * self.diffuse.pdf(wo, wi)
+            + probabilities[1] * self.dielectric.pdf(wo, wi)
+            + probabilities[2] * self.conductor.pdf(wo, wi)
+    }
+}
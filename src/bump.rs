@@ -0,0 +1,87 @@
+extern crate cgmath;
+
+use crate::bsdf::luminance;
+use crate::common::ColliderResult;
+use crate::texture::ImageTexture;
+
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+// Surface detail that bends the shading normal of hits, applied by World::intersect to the
+// hits on materials that have one. Needs texture coordinates and their derivatives.
+pub trait NormalPerturbation: Sync + Send {
+    fn perturb(&self, collision: &mut ColliderResult);
+}
+
+// Tangent space normal map with +Y along v (OpenGL convention), loaded with
+// ImageTexture::load_data. strength scales the tilt, 1 is as authored.
+pub struct NormalMap {
+    image: ImageTexture,
+    strength: f64,
+}
+
+impl NormalMap {
+    pub fn new(image: ImageTexture, strength: f64) -> NormalMap {
+        NormalMap { image, strength }
+    }
+}
+
+impl NormalPerturbation for NormalMap {
+    fn perturb(&self, collision: &mut ColliderResult) {
+        let normal = collision.normal;
+        let tangent = collision.dpdu - normal * normal.dot(collision.dpdu);
+        if tangent.magnitude2() <= 0. {
+            return;
+        }
+        let tangent = tangent.normalize();
+        // Mirrored texture coordinates flip the bitangent
+        let mut bitangent = normal.cross(tangent);
+        if bitangent.dot(collision.dpdv) < 0. {
+            bitangent = -bitangent;
+        }
+        let texel = self.image.sample(collision.uv) * 2. - Vector3 {x: 1., y: 1., z: 1.};
+        let perturbed = tangent * (texel.x * self.strength) + bitangent * (texel.y * self.strength) + normal * texel.z;
+        if perturbed.magnitude2() > 0. {
+            collision.normal = perturbed.normalize();
+        }
+    }
+}
+
+// Grayscale height map, loaded with ImageTexture::load_data, with white raised by height
+// world units. The slopes come from finite differences of one texel, so it works on any
+// surface with texture coordinates, spheres included.
+pub struct BumpMap {
+    image: ImageTexture,
+    height: f64,
+}
+
+impl BumpMap {
+    pub fn new(image: ImageTexture, height: f64) -> BumpMap {
+        BumpMap { image, height }
+    }
+
+    fn height_at(&self, uv: Vector2<f64>) -> f64 {
+        luminance(self.image.sample(uv)) * self.height
+    }
+}
+
+impl NormalPerturbation for BumpMap {
+    fn perturb(&self, collision: &mut ColliderResult) {
+        let du = 1. / self.image.width() as f64;
+        let dv = 1. / self.image.height() as f64;
+        let uv = collision.uv;
+        let h = self.height_at(uv);
+        let dhdu = (self.height_at(uv + Vector2 {x: du, y: 0.}) - h) / du;
+        let dhdv = (self.height_at(uv + Vector2 {x: 0., y: dv}) - h) / dv;
+
+        // Tangents of the displaced surface, ignoring the change of the normal itself
+        let normal = collision.normal;
+        let dpdu = collision.dpdu + normal * dhdu;
+        let dpdv = collision.dpdv + normal * dhdv;
+        let perturbed = dpdu.cross(dpdv);
+        if perturbed.magnitude2() <= 0. {
+            return;
+        }
+        let perturbed = perturbed.normalize();
+        collision.normal = if perturbed.dot(normal) < 0. { -perturbed } else { perturbed };
+    }
+}
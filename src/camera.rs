@@ -0,0 +1,178 @@
+extern crate cgmath;
+
+use crate::common::Ray;
+use crate::sampling;
+
+use cgmath::{Deg, InnerSpace, Point3, Rad, Vector3};
+
+pub struct Camera {
+    eye: Point3<f64>,
+    target: Point3<f64>,
+    up: Vector3<f64>,
+    fov: Deg<f64>,
+    aspect: f64,
+    // Thin lens: a zero aperture is a pinhole camera with everything in focus
+    aperture: f64,
+    focus_distance: f64,
+    // Orthonormal basis: u points right, v points up, w points away from the target
+    u: Vector3<f64>,
+    v: Vector3<f64>,
+    w: Vector3<f64>,
+    half_width: f64,
+    half_height: f64,
+}
+
+impl Camera {
+    // fov is the vertical field of view, aspect is width / height
+    pub fn new(eye: Point3<f64>, target: Point3<f64>, up: Vector3<f64>, fov: Deg<f64>, aspect: f64) -> Camera {
+        let mut camera = Camera {
+            eye,
+            target,
+            up,
+            fov,
+            aspect,
+            aperture: 0.,
+            focus_distance: (target - eye).magnitude(),
+            u: Vector3 {x: 1., y: 0., z: 0.},
+            v: Vector3 {x: 0., y: 1., z: 0.},
+            w: Vector3 {x: 0., y: 0., z: 1.},
+            half_width: 0.,
+            half_height: 0.,
+        };
+        camera.update();
+        camera
+    }
+
+    pub fn look_at(&mut self, eye: Point3<f64>, target: Point3<f64>, up: Vector3<f64>) {
+        self.eye = eye;
+        self.target = target;
+        self.up = up;
+        self.update();
+    }
+
+    pub fn set_fov(&mut self, fov: Deg<f64>) {
+        self.fov = fov;
+        self.update();
+    }
+
+    pub fn set_aspect(&mut self, aspect: f64) {
+        self.aspect = aspect;
+        self.update();
+    }
+
+    // Radius of the lens disk and distance from the eye to the plane that is in perfect focus
+    pub fn set_lens(&mut self, aperture: f64, focus_distance: f64) {
+        self.aperture = aperture;
+        self.focus_distance = focus_distance;
+    }
+
+    // Focuses on the plane through the given point
+    pub fn focus_on(&mut self, point: Point3<f64>) {
+        self.focus_distance = self.depth(point);
+    }
+
+    pub fn eye(&self) -> Point3<f64> {
+        self.eye
+    }
+
+    pub fn target(&self) -> Point3<f64> {
+        self.target
+    }
+
+    pub fn fov(&self) -> Deg<f64> {
+        self.fov
+    }
+
+    pub fn aspect(&self) -> f64 {
+        self.aspect
+    }
+
+    pub fn aperture(&self) -> f64 {
+        self.aperture
+    }
+
+    pub fn focus_distance(&self) -> f64 {
+        self.focus_distance
+    }
+
+    // Distance of a point in front of the camera along the viewing direction (Z depth)
+    pub fn depth(&self, point: Point3<f64>) -> f64 {
+        (point - self.eye).dot(-self.w)
+    }
+
+    // Primary ray through the image plane, s goes left to right and t goes top to bottom (both 0 to 1)
+    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let x = (2. * s - 1.) * self.half_width;
+        let y = (1. - 2. * t) * self.half_height;
+        let direction = self.u * x + self.v * y - self.w;
+        if self.aperture <= 0. {
+            return Ray {
+                origin: self.eye,
+                direction: direction.normalize(),
+                bounce: 0,
+            };
+        }
+
+        // Jitter the origin over the lens and aim at where the pinhole ray meets the focal plane
+        let focus_point = self.eye + direction * self.focus_distance;
+        let (lx, ly) = sampling::concentric_disk(sampling::random(), sampling::random());
+        let origin = self.eye + (self.u * lx + self.v * ly) * self.aperture;
+        Ray {
+            origin,
+            direction: (focus_point - origin).normalize(),
+            bounce: 0,
+        }
+    }
+
+    fn update(&mut self) {
+        self.w = (self.eye - self.target).normalize();
+        let mut u = self.up.cross(self.w);
+        if u.magnitude2() < 1e-12 {
+            // Looking straight along up (or up is zero): roll around the world axis that is
+            // furthest from the view direction instead
+            let axis = if self.w.x.abs() < self.w.y.abs().min(self.w.z.abs()) {
+                Vector3::unit_x()
+            } else if self.w.y.abs() < self.w.z.abs() {
+                Vector3::unit_y()
+            } else {
+                Vector3::unit_z()
+            };
+            u = axis.cross(self.w);
+        }
+        self.u = u.normalize();
+        self.v = self.w.cross(self.u);
+        self.half_height = (Rad::from(self.fov).0 / 2.).tan();
+        self.half_width = self.aspect * self.half_height;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_finite(v: Vector3<f64>) {
+        assert!(v.x.is_finite() && v.y.is_finite() && v.z.is_finite(), "{:?}", v);
+    }
+
+    #[test]
+    fn looking_along_up_gives_a_valid_basis() {
+        let up = Vector3 {x: 0., y: 1., z: 0.};
+        for target in &[Point3 {x: 0., y: 5., z: 0.}, Point3 {x: 0., y: -5., z: 0.}] {
+            let camera = Camera::new(Point3 {x: 0., y: 0., z: 0.}, *target, up, Deg(40.), 1.);
+            let ray = camera.get_ray(0.25, 0.75);
+            assert_finite(ray.direction);
+            assert!((camera.u.magnitude() - 1.).abs() < 1e-9);
+            assert!(camera.u.dot(camera.w).abs() < 1e-9);
+            assert!(camera.v.dot(camera.w).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn center_ray_points_at_the_target() {
+        let eye = Point3 {x: 1., y: 2., z: 3.};
+        let target = Point3 {x: -2., y: 0., z: 7.};
+        let camera = Camera::new(eye, target, Vector3 {x: 0., y: 1., z: 0.}, Deg(60.), 1.5);
+        let ray = camera.get_ray(0.5, 0.5);
+        assert!((ray.direction - (target - eye).normalize()).magnitude() < 1e-9);
+    }
+}
@@ -4,8 +4,43 @@ use crate::material::Material;
 use crate::tracer::RayTracer;
 use crate::lighting::LightSource;
 use crate::geometry::aabb::AABB;
+use crate::tonemap::{srgb_decode, srgb_encode};
 
-use cgmath::{EuclideanSpace, Point3, Vector3};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2, Vector3};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// How far rays leaving a surface start off it, so they don't hit it again (shadow acne)
+pub const SURFACE_OFFSET: f64 = 1e-4;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// Silences the progress output printed while loading and rendering
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+// print! and println! for progress output, skipped in quiet mode
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::common::quiet() {
+            print!($($arg)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! statusln {
+    ($($arg:tt)*) => {
+        if !$crate::common::quiet() {
+            println!($($arg)*);
+        }
+    };
+}
 
 pub struct World {
     pub entities: Vec<Box<dyn Entity>>,
@@ -14,6 +49,46 @@ pub struct World {
     pub ambient: f64,
 }
 
+impl World {
+    // Closest hit over every entity, along with the index of the entity that was hit. The
+    // normal includes the normal or bump map of the material.
+    pub fn intersect(&self, ray: &Ray) -> Option<(usize, ColliderResult)> {
+        let mut min_distance = f64::MAX;
+        let mut closest = None;
+        for (index, entity) in self.entities.iter().enumerate() {
+            let result = entity.collide(ray);
+            if result.collision {
+                let distance = (result.position - ray.origin).magnitude2();
+                if distance < min_distance {
+                    min_distance = distance;
+                    closest = Some((index, result));
+                }
+            }
+        }
+        if let Some((_, result)) = closest.as_mut() {
+            let perturbation = result.material.as_ref().and_then(|m| m.normal_perturbation.clone());
+            if let Some(perturbation) = perturbation {
+                perturbation.perturb(result);
+            }
+        }
+        closest
+    }
+
+    // Whether anything lies between origin and max_distance along the (normalized) direction
+    pub fn occluded(&self, origin: Point3<f64>, direction: Vector3<f64>, max_distance: f64) -> bool {
+        let ray = Ray {
+            origin,
+            direction,
+            bounce: 0,
+        };
+        let max_distance2 = max_distance * max_distance;
+        self.entities.iter().any(|entity| {
+            let result = entity.collide(&ray);
+            result.collision && (result.position - origin).magnitude2() < max_distance2
+        })
+    }
+}
+
 pub struct Ray {
     pub origin: Point3<f64>,
     pub direction: Vector3<f64>,
@@ -36,6 +111,42 @@ pub trait RayBehavior: Sync + Send {
     ) -> Option<Vector3<f64>>;
 
     fn mix(&self) -> f64;
+
+    // Path tracing. wo points back along the incoming ray and wi to where light arrives from,
+    // both away from the surface. The defaults reflect nothing, so behaviors that don't
+    // implement these are skipped by the path tracer.
+
+    // Fraction of the light arriving from wi that leaves towards wo, per steradian and before
+    // the cosine term. Mirror-like behaviors return nothing here and only sample.
+    fn evaluate(&self, _collision: &ColliderResult, _wo: Vector3<f64>, _wi: Vector3<f64>) -> Vector3<f64> {
+        Vector3 {x: 0., y: 0., z: 0.}
+    }
+
+    // Picks a direction to continue the path in, u and v are uniform in [0, 1)
+    fn sample(&self, _collision: &ColliderResult, _wo: Vector3<f64>, _u: f64, _v: f64) -> Option<ScatterSample> {
+        None
+    }
+
+    // Density of sample picking wi, per solid angle
+    fn pdf(&self, _collision: &ColliderResult, _wo: Vector3<f64>, _wi: Vector3<f64>) -> f64 {
+        0.
+    }
+
+    // Fraction of the light surviving a ray segment of length distance inside the surface,
+    // for closed surfaces filled with an absorbing medium like colored glass. Applied to rays
+    // hitting the surface from behind.
+    fn transmittance(&self, _distance: f64) -> Vector3<f64> {
+        Vector3 {x: 1., y: 1., z: 1.}
+    }
+}
+
+pub struct ScatterSample {
+    pub direction: Vector3<f64>,
+    // evaluate * cos / pdf, or the reflected fraction for specular samples
+    pub weight: Vector3<f64>,
+    pub pdf: f64,
+    // Perfect mirrors and glass, which light sampling can never hit
+    pub specular: bool,
 }
 
 pub trait Entity: Sync + Send {
@@ -49,7 +160,15 @@ pub trait Entity: Sync + Send {
 pub struct ColliderResult {
     pub collision: bool,
     pub position: Point3<f64>,
+    // The position relative to the entity that was hit, for textures that follow it around
+    pub object_position: Point3<f64>,
     pub normal: Vector3<f64>,
+    // Texture coordinates, (0, 0) for surfaces without any
+    pub uv: Vector2<f64>,
+    // How the position changes along the texture coordinates, for orienting normal maps.
+    // Zero for surfaces without texture coordinates.
+    pub dpdu: Vector3<f64>,
+    pub dpdv: Vector3<f64>,
     pub material: Option<Material>
 }
 
@@ -63,35 +182,66 @@ impl ColliderResult {
                 y: 0.0,
                 z: 0.0,
             },
+            object_position: Point3 {x: 0.0, y: 0.0, z: 0.0},
             normal: Vector3 {
                 x: 0.0,
                 y: 0.0,
                 z: 0.0,
             },
+            uv: Vector2 {x: 0.0, y: 0.0},
+            dpdu: Vector3 {x: 0.0, y: 0.0, z: 0.0},
+            dpdv: Vector3 {x: 0.0, y: 0.0, z: 0.0},
         }
     }
 }
 
+// Clamps a linear color to [0, 1] and sRGB encodes it. Use an OutputTransform first to
+// bring HDR values into range.
 pub fn vec_rgb(v: Vector3<f64>) -> image::Rgb<u8> {
-    image::Rgb([
-        (v.x * 255.0) as u8,
-        (v.y * 255.0) as u8,
-        (v.z * 255.0) as u8,
-    ])
+    let encode = |c: f64| (srgb_encode(c.clamp(0., 1.)) * 255.0).round() as u8;
+    image::Rgb([encode(v.x), encode(v.y), encode(v.z)])
 }
 
+// Decodes an sRGB pixel (textures, cubemaps) to linear
 pub fn rgb_vec(i: image::Rgb<u8>) -> Vector3<f64> {
     color_vec(i[0], i[1], i[2])
 }
 
+// 0-255 colors are taken to be sRGB, as picked in any color picker, and decoded to linear
 pub fn color_vec(r: u8, g: u8, b: u8) -> Vector3<f64> {
     Vector3 {
-        x: (r as f64) / 255.0,
-        y: (g as f64) / 255.0,
-        z: (b as f64) / 255.0,
+        x: srgb_decode((r as f64) / 255.0),
+        y: srgb_decode((g as f64) / 255.0),
+        z: srgb_decode((b as f64) / 255.0),
+    }
+}
+
+// Start of a ray leaving the surface at position in the given direction, pushed off the
+// surface on the side the ray goes to
+pub fn offset_origin(position: Point3<f64>, normal: Vector3<f64>, direction: Vector3<f64>) -> Point3<f64> {
+    if normal.dot(direction) < 0. {
+        position - normal * SURFACE_OFFSET
+    } else {
+        position + normal * SURFACE_OFFSET
     }
 }
 
+// Two unit vectors completing the unit vector n to an orthonormal basis (Duff et al.)
+pub fn tangent_frame(n: Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+    let sign = 1f64.copysign(n.z);
+    let a = -1. / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vector3 {x: 1. + sign * n.x * n.x * a, y: sign * b, z: -sign * n.x},
+        Vector3 {x: b, y: sign + n.y * n.y * a, z: -n.y},
+    )
+}
+
+// The normal flipped to the side of w
+pub fn facing(normal: Vector3<f64>, w: Vector3<f64>) -> Vector3<f64> {
+    if normal.dot(w) < 0. { -normal } else { normal }
+}
+
 pub fn lerp(v1: Vector3<f64>, v2: Vector3<f64>, amount: f64) -> Vector3<f64> {
     Vector3 {
         x: v1.x + (v2.x - v1.x) * amount,
@@ -0,0 +1,131 @@
+extern crate cgmath;
+
+use crate::aov::{AovAccumulator, AovBuffers};
+use crate::framebuffer::Framebuffer;
+use crate::scheduler::{Tile, TileScheduler};
+
+use cgmath::Vector3;
+use std::sync::Mutex;
+
+// Running filter-weighted sums for one pixel. Adding samples one at a time in the same
+// order always gives the same result, however the samples are split into passes.
+#[derive(Copy, Clone)]
+pub struct PixelAccumulator {
+    color_sum: Vector3<f64>,
+    plain_sum: Vector3<f64>,
+    weight_sum: f64,
+    abs_weight_sum: f64,
+    samples: u32,
+}
+
+impl PixelAccumulator {
+    pub fn new() -> PixelAccumulator {
+        PixelAccumulator {
+            color_sum: Vector3 {x: 0., y: 0., z: 0.},
+            plain_sum: Vector3 {x: 0., y: 0., z: 0.},
+            weight_sum: 0.,
+            abs_weight_sum: 0.,
+            samples: 0,
+        }
+    }
+
+    pub fn add(&mut self, color: Vector3<f64>, weight: f64) {
+        self.color_sum += color * weight;
+        self.plain_sum += color;
+        self.weight_sum += weight;
+        self.abs_weight_sum += weight.abs();
+        self.samples += 1;
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn resolve(&self) -> Vector3<f64> {
+        if self.samples == 0 {
+            return Vector3 {x: 0., y: 0., z: 0.};
+        }
+        // Filters with negative lobes can (nearly) cancel out at low sample counts
+        if self.weight_sum <= self.abs_weight_sum * 0.1 {
+            return self.plain_sum / self.samples as f64;
+        }
+        let color = self.color_sum / self.weight_sum;
+        Vector3 {x: color.x.max(0.), y: color.y.max(0.), z: color.z.max(0.)}
+    }
+}
+
+impl Default for PixelAccumulator {
+    fn default() -> Self {
+        PixelAccumulator::new()
+    }
+}
+
+// Accumulators of one tile, the AOVs are empty unless the film records them
+struct TilePixels {
+    beauty: Vec<PixelAccumulator>,
+    aovs: Vec<AovAccumulator>,
+}
+
+// Floating point accumulation buffer, stored as one buffer per tile so render threads
+// can add samples to their tile while the main thread takes snapshots of the rest.
+pub struct Film {
+    image_size: (u32, u32),
+    tiles: Vec<(Tile, Mutex<TilePixels>)>,
+    has_aovs: bool,
+}
+
+impl Film {
+    pub fn new(scheduler: &TileScheduler, image_size: (u32, u32), has_aovs: bool) -> Film {
+        let tiles = (0..scheduler.tile_count())
+            .map(|i| {
+                let tile = scheduler.tile(i);
+                let aov_count = if has_aovs { tile.pixel_count() } else { 0 };
+                let pixels = TilePixels {
+                    beauty: vec![PixelAccumulator::new(); tile.pixel_count()],
+                    aovs: vec![AovAccumulator::new(); aov_count],
+                };
+                (tile, Mutex::new(pixels))
+            })
+            .collect();
+        Film { image_size, tiles, has_aovs }
+    }
+
+    // Calls add_samples with the image coordinates and accumulators of every pixel in the
+    // tile, the AOV accumulator is None when the film doesn't record AOVs
+    pub fn accumulate<F>(&self, tile: Tile, mut add_samples: F)
+    where
+        F: FnMut(u32, u32, &mut PixelAccumulator, Option<&mut AovAccumulator>),
+    {
+        let mut pixels = self.tiles[tile.index].1.lock().unwrap();
+        let TilePixels { beauty, aovs } = &mut *pixels;
+        let mut aovs = aovs.iter_mut();
+        for ((x, y), pixel) in tile.coordinates().zip(beauty.iter_mut()) {
+            add_samples(x, y, pixel, aovs.next());
+        }
+    }
+
+    pub fn to_framebuffer(&self) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(self.image_size.0, self.image_size.1);
+        for (tile, pixels) in self.tiles.iter() {
+            let pixels = pixels.lock().unwrap();
+            for ((x, y), pixel) in tile.coordinates().zip(pixels.beauty.iter()) {
+                framebuffer.set(x, y, pixel.resolve());
+            }
+        }
+        framebuffer
+    }
+
+    pub fn to_aov_buffers(&self) -> Option<AovBuffers> {
+        if !self.has_aovs {
+            return None;
+        }
+        let mut buffers = AovBuffers::new(self.image_size.0, self.image_size.1);
+        for (tile, pixels) in self.tiles.iter() {
+            let pixels = pixels.lock().unwrap();
+            for ((x, y), pixel) in tile.coordinates().zip(pixels.aovs.iter()) {
+                buffers.set(x, y, pixel.resolve());
+            }
+        }
+        Some(buffers)
+    }
+}
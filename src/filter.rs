@@ -0,0 +1,219 @@
+// Pixel reconstruction filters. Samples are spread over the filter's support around the
+// pixel center and the pixel value is their filter-weighted average.
+pub trait PixelFilter: Sync + Send {
+    // Half-width of the support in pixels
+    fn radius(&self) -> f64;
+    // Weight of a sample at offset (x, y) from the pixel center
+    fn evaluate(&self, x: f64, y: f64) -> f64;
+}
+
+pub struct BoxFilter {
+    radius: f64,
+}
+
+impl BoxFilter {
+    pub fn new(radius: f64) -> BoxFilter {
+        BoxFilter { radius }
+    }
+}
+
+impl Default for BoxFilter {
+    fn default() -> Self {
+        BoxFilter::new(0.5)
+    }
+}
+
+impl PixelFilter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, _x: f64, _y: f64) -> f64 {
+        1.
+    }
+}
+
+pub struct TentFilter {
+    radius: f64,
+}
+
+impl TentFilter {
+    pub fn new(radius: f64) -> TentFilter {
+        TentFilter { radius }
+    }
+}
+
+impl Default for TentFilter {
+    fn default() -> Self {
+        TentFilter::new(1.)
+    }
+}
+
+impl PixelFilter for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.) * (self.radius - y.abs()).max(0.)
+    }
+}
+
+pub struct GaussianFilter {
+    radius: f64,
+    alpha: f64,
+    // Value at the edge of the support, subtracted so the filter falls off to zero
+    edge: f64,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64, alpha: f64) -> GaussianFilter {
+        GaussianFilter {
+            radius,
+            alpha,
+            edge: (-alpha * radius * radius).exp(),
+        }
+    }
+
+    fn gaussian(&self, d: f64) -> f64 {
+        ((-self.alpha * d * d).exp() - self.edge).max(0.)
+    }
+}
+
+impl Default for GaussianFilter {
+    fn default() -> Self {
+        GaussianFilter::new(1.5, 2.)
+    }
+}
+
+impl PixelFilter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.gaussian(x) * self.gaussian(y)
+    }
+}
+
+// Mitchell-Netravali cubic, b = c = 1/3 is the recommended compromise between ringing and blur
+pub struct MitchellFilter {
+    radius: f64,
+    b: f64,
+    c: f64,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64, b: f64, c: f64) -> MitchellFilter {
+        MitchellFilter { radius, b, c }
+    }
+
+    // x is in [-1, 1] over the support
+    fn mitchell(&self, x: f64) -> f64 {
+        let x = (2. * x).abs();
+        let (b, c) = (self.b, self.c);
+        let result = if x > 2. {
+            0.
+        } else if x > 1. {
+            (-b - 6. * c) * x.powi(3) + (6. * b + 30. * c) * x.powi(2)
+                + (-12. * b - 48. * c) * x + (8. * b + 24. * c)
+        } else {
+            (12. - 9. * b - 6. * c) * x.powi(3) + (-18. + 12. * b + 6. * c) * x.powi(2)
+                + (6. - 2. * b)
+        };
+        result / 6.
+    }
+}
+
+impl Default for MitchellFilter {
+    fn default() -> Self {
+        MitchellFilter::new(2., 1. / 3., 1. / 3.)
+    }
+}
+
+impl PixelFilter for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.mitchell(x / self.radius) * self.mitchell(y / self.radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters() -> Vec<Box<dyn PixelFilter>> {
+        vec![
+            Box::new(BoxFilter::default()),
+            Box::new(TentFilter::default()),
+            Box::new(GaussianFilter::default()),
+            Box::new(MitchellFilter::default()),
+        ]
+    }
+
+    #[test]
+    fn weights_are_symmetric() {
+        for filter in filters() {
+            for &(x, y) in &[(0.1, 0.3), (0.4, 0.2), (0.45, 0.05)] {
+                let (x, y) = (x * filter.radius() * 2., y * filter.radius() * 2.);
+                let weight = filter.evaluate(x, y);
+                assert!((filter.evaluate(-x, y) - weight).abs() < 1e-12);
+                assert!((filter.evaluate(x, -y) - weight).abs() < 1e-12);
+                assert!((filter.evaluate(y, x) - weight).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn weights_peak_at_the_center() {
+        for filter in filters() {
+            let center = filter.evaluate(0., 0.);
+            assert!(center > 0.);
+            for i in 1..10 {
+                let d = filter.radius() * i as f64 / 10.;
+                assert!(filter.evaluate(d, 0.) <= center);
+                assert!(filter.evaluate(d, d) <= center);
+            }
+        }
+    }
+
+    #[test]
+    fn weights_fall_to_zero_at_the_edge() {
+        let filters: Vec<Box<dyn PixelFilter>> = vec![
+            Box::new(TentFilter::default()),
+            Box::new(GaussianFilter::default()),
+            Box::new(MitchellFilter::default()),
+        ];
+        for filter in filters {
+            let r = filter.radius();
+            assert!(filter.evaluate(r, 0.).abs() < 1e-12);
+            assert!(filter.evaluate(0., -r).abs() < 1e-12);
+            assert_eq!(filter.evaluate(r * 1.5, 0.), 0.);
+        }
+    }
+
+    #[test]
+    fn tent_weights_fall_off_linearly() {
+        let filter = TentFilter::new(2.);
+        assert!((filter.evaluate(0., 0.) - 4.).abs() < 1e-12);
+        assert!((filter.evaluate(1., 0.) - 2.).abs() < 1e-12);
+        assert!((filter.evaluate(1., 1.) - 1.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn mitchell_is_continuous_and_integrates_to_one() {
+        let filter = MitchellFilter::default();
+        // The two cubic pieces meet halfway to the edge
+        let (below, above) = (filter.mitchell(0.5 - 1e-9), filter.mitchell(0.5 + 1e-9));
+        assert!((below - above).abs() < 1e-6);
+        // Over [-2, 2] in the kernel's own units
+        let steps = 10000;
+        let integral: f64 = (0..steps)
+            .map(|i| filter.mitchell(-1. + 2. * (i as f64 + 0.5) / steps as f64) * 4. / steps as f64)
+            .sum();
+        assert!((integral - 1.).abs() < 1e-6, "{}", integral);
+    }
+}
@@ -0,0 +1,103 @@
+extern crate cgmath;
+
+use crate::output::{self, ExrChannel, ExrPrecision};
+use crate::tonemap::OutputTransform;
+
+use anyhow::Result;
+use cgmath::Vector3;
+use image::{ImageBuffer, Rgb, RgbImage};
+use std::path::Path;
+
+// Linear floating point RGB image, as rendered and before any conversion for display
+#[derive(Clone)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vector3<f32>>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Vector3 {x: 0., y: 0., z: 0.}; (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Vector3<f64> {
+        self.pixels[self.index(x, y)].cast().unwrap()
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Vector3<f64>) {
+        let index = self.index(x, y);
+        self.pixels[index] = color.cast().unwrap();
+    }
+
+    // Row by row, top to bottom
+    pub fn pixels(&self) -> &[Vector3<f32>] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Vector3<f32>] {
+        &mut self.pixels
+    }
+
+    // Display image, quantized through the output transform
+    pub fn to_rgb8(&self, transform: &OutputTransform) -> RgbImage {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| transform.to_rgb8(self.get(x, y)))
+    }
+
+    pub fn to_rgb32f(&self) -> ImageBuffer<Rgb<f32>, Vec<f32>> {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let p = self.pixels[self.index(x, y)];
+            Rgb([p.x, p.y, p.z])
+        })
+    }
+
+    // One channel of every pixel, 0 = red, 1 = green, 2 = blue
+    pub fn channel(&self, channel: usize) -> Vec<f32> {
+        self.pixels.iter().map(|p| p[channel]).collect()
+    }
+
+    // Saves in the format matching the file extension: .exr and .hdr keep the linear
+    // radiance, anything else goes through the output transform to 8 bits
+    pub fn save<P: AsRef<Path>>(&self, path: P, transform: &OutputTransform) -> Result<()> {
+        match output::extension(&path).as_str() {
+            "exr" => self.save_exr(path, transform.exr_precision),
+            "hdr" => self.save_hdr(path),
+            _ => Ok(self.to_rgb8(transform).save(path)?),
+        }
+    }
+
+    pub fn save_exr<P: AsRef<Path>>(&self, path: P, precision: ExrPrecision) -> Result<()> {
+        output::write_exr(path, (self.width, self.height), self.exr_channels(""), precision)
+    }
+
+    pub fn save_hdr<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let pixels: Vec<Rgb<f32>> = self.pixels.iter().map(|p| Rgb([p.x, p.y, p.z])).collect();
+        output::write_hdr(path, (self.width, self.height), &pixels)
+    }
+
+    // R, G and B channels, prefixed with "layer." unless the layer name is empty
+    pub fn exr_channels(&self, layer: &str) -> Vec<ExrChannel> {
+        ["R", "G", "B"].iter().enumerate()
+            .map(|(i, c)| {
+                let name = if layer.is_empty() { c.to_string() } else { format!("{}.{}", layer, c) };
+                ExrChannel::new(&name, self.channel(i))
+            })
+            .collect()
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+}
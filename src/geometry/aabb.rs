@@ -5,7 +5,7 @@ use std::{ops::Deref};
 use crate::material::Material;
 use crate::common::{Entity, ColliderResult, Ray};
 
-use cgmath::{EuclideanSpace, Point3, Vector3};
+use cgmath::{EuclideanSpace, Point3, Vector2, Vector3};
 
 #[derive(Copy, Clone)]
 pub struct AABB {
@@ -18,13 +18,9 @@ impl AABB {
         AABB { min, max }
     }
 
-    pub fn default() -> Self {
-        AABB::new(Point3 {x: 0., y: 0., z: 0.}, Point3 {x: 0., y: 0., z: 0.})
-    }
-
     pub fn from_entities<T: Entity + ?Sized> (entities: impl Iterator<Item = impl Deref<Target = T>>) -> Self {
-        let mut min = Point3{x: std::f64::MAX, y: std::f64::MAX, z: std::f64::MAX};
-        let mut max = Point3{x: std::f64::MIN, y: std::f64::MIN, z: std::f64::MIN};
+        let mut min = Point3{x: f64::MAX, y: f64::MAX, z: f64::MAX};
+        let mut max = Point3{x: f64::MIN, y: f64::MIN, z: f64::MIN};
         for entity in entities {
             let bb = entity.bounding_box();
             if bb.min.x < min.x { min.x = bb.min.x; }
@@ -44,6 +40,17 @@ impl AABB {
         if point.z > self.max.z || point.z < self.min.z { return false; }
         true
     }
+
+    // Contains, with the box grown by margin on every side
+    pub fn contains_with_margin (&self, point: &Point3<f64>, margin: f64) -> bool {
+        (0..3).all(|i| point[i] >= self.min[i] - margin && point[i] <= self.max[i] + margin)
+    }
+}
+
+impl Default for AABB {
+    fn default() -> Self {
+        AABB::new(Point3 {x: 0., y: 0., z: 0.}, Point3 {x: 0., y: 0., z: 0.})
+    }
 }
 
 impl Entity for AABB {
@@ -76,7 +83,7 @@ impl Entity for AABB {
         let times = (0..3).map(|i| 
                 if !one_over_dir[i].is_finite() {
                     if candidate_dist[i] == 0. {-1.}
-                    else {std::f64::MAX}
+                    else {f64::MAX}
                 } else { candidate_dist[i] * one_over_dir[i] });
         if inside {
             hit_point = ray.parameterize(
@@ -93,9 +100,13 @@ impl Entity for AABB {
 
         ColliderResult {
             normal: Vector3 {x: 0., y: 0., z: 0.},
+            uv: Vector2 {x: 0., y: 0.},
+            dpdu: Vector3 {x: 0., y: 0., z: 0.},
+            dpdv: Vector3 {x: 0., y: 0., z: 0.},
             collision: true,
             material: None,
-            position: Point3::from_vec(hit_point)
+            position: Point3::from_vec(hit_point),
+            object_position: Point3::from_vec(hit_point)
         }
     }
 
@@ -32,12 +32,12 @@ impl <T> KDTree<T> {
         Some(node)
     }
 
-    pub fn translate_nodes(&self, vec: Vector3<f64>) {
+    pub fn translate_nodes(&mut self, vec: Vector3<f64>) {
         if self.leaf.is_none() {
-            let partition_mut = self.partition.as_ref().unwrap() as *const f64 as *mut f64;
-            unsafe {*partition_mut += vec[self.axis.unwrap()]};
-            self.left.as_ref().unwrap().translate_nodes(vec);
-            self.right.as_ref().unwrap().translate_nodes(vec);
+            let axis = self.axis.unwrap();
+            *self.partition.as_mut().unwrap() += vec[axis];
+            self.left.as_mut().unwrap().translate_nodes(vec);
+            self.right.as_mut().unwrap().translate_nodes(vec);
         }
     }
 }
@@ -67,7 +67,7 @@ impl <T: Entity> KDTree<T> {
         let get_max_axis = |a: &T| a.bounding_box().max[axis];
 
         let median_pos = entities.len() / 2;
-        entities.sort_unstable_by(|a, b| get_min_axis(&a).partial_cmp(&get_min_axis(&b)).unwrap());
+        entities.sort_unstable_by(|a, b| get_min_axis(a).partial_cmp(&get_min_axis(b)).unwrap());
         let mut partition = get_min_axis(&entities[median_pos]);
         
         if (partition - bounding_box.min[axis]).abs() < 0.001 || (partition - bounding_box.max[axis]).abs() < 0.001 {
@@ -79,13 +79,13 @@ impl <T: Entity> KDTree<T> {
         let right_orig_length = right_half.len();
 
         for ent in &entities {
-            if get_max_axis(&ent) >= partition {
+            if get_max_axis(ent) >= partition {
                 right_half.push(ent.clone());
             }
         }
 
         for ent in &right_half[..right_orig_length] {
-            if get_min_axis(&ent) < partition {
+            if get_min_axis(ent) < partition {
                 entities.push(ent.clone());
             }
         }
@@ -190,15 +190,21 @@ impl <T: Entity> KDTree<T> {
     }
 
     pub fn collide(&self, ray: &Ray) -> ColliderResult {
-        let mut collision = self.aa_bb.collide(ray);
-        if !collision.collision {
-            return ColliderResult::negative();
-        }
-        let mut point = collision.position + ray.direction * 0.01;
+        // Rays starting inside the tree (shadow and reflection rays) start in the leaf around them
+        let mut point = if self.aa_bb.contains(&ray.origin) {
+            ray.origin
+        } else {
+            let collision = self.aa_bb.collide(ray);
+            if !collision.collision {
+                return ColliderResult::negative();
+            }
+            collision.position + ray.direction * 0.01
+        };
+        let mut collision;
         let mut next_leaf:Option<&KDTree<T>> = self.find_point(point);
         loop {
             if let Some(node) = next_leaf {
-                let mut min_distance = std::f64::MAX;
+                let mut min_distance = f64::MAX;
                 let mut closest: Option<ColliderResult> = None;
                 for entity in node.leaf.as_ref().unwrap() {
                     collision = entity.collide(ray);
@@ -210,8 +216,12 @@ impl <T: Entity> KDTree<T> {
                         }
                     }
                 }
+                // A hit outside this leaf may be behind something in the leaves in between,
+                // the entity is in the leaf around the hit as well so it is found again there
                 if let Some(col) = closest {
-                    return col;
+                    if node.aa_bb.contains_with_margin(&col.position, 1e-6) {
+                        return col;
+                    }
                 }
                 let new_ray = Ray {
                     origin: point,
@@ -5,10 +5,13 @@ use crate::common::{Entity, ColliderResult, Ray};
 use crate::geometry::triangle::Triangle;
 use crate::geometry::kdtree::KDTree;
 use crate::geometry::aabb::AABB;
+use crate::mtl::load_mtl;
+use crate::statusln;
 
-use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
-use obj::{load_obj, Obj}; 
-use std::{fs::File, sync::{Arc}};
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector2, Vector3};
+use obj::raw::{object::Polygon, parse_obj, RawObj};
+use anyhow::{Context, Result};
+use std::{collections::HashMap, fs::File, path::Path, sync::{Arc}};
 use std::io::BufReader;
 use crate::cgmath::Transform;
 
@@ -21,30 +24,96 @@ pub struct Model {
 }
 
 impl Model {
-    pub fn new(path: &str, material: Material, position: Point3<f64>, scale: Vector3<f64>) -> Model {
-        println!("Opening model @ {}", path);
-        let input = BufReader::new(File::open(path).unwrap());
-        let model: Obj = load_obj(input).unwrap();
+    pub fn new(path: &str, material: Material, position: Point3<f64>, scale: Vector3<f64>) -> Result<Model> {
+        statusln!("Opening model @ {}", path);
+        let obj = read_obj(path)?;
+        Ok(Model::from_obj(&obj, material, position, scale, |_| None))
+    }
+
+    // With the materials of the MTL libraries the OBJ file references, picked per face by
+    // usemtl. Faces without one, or with one missing from the libraries, use fallback, and so
+    // do all faces of a library that doesn't exist.
+    // Materials get ids from next_id in file order, starting with fallback.
+    pub fn new_with_mtl(
+        path: &str,
+        mut fallback: Material,
+        position: Point3<f64>,
+        scale: Vector3<f64>,
+        next_id: &mut u32,
+    ) -> Result<Model> {
+        statusln!("Opening model @ {}", path);
+        let obj = read_obj(path)?;
+        let base = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+        let mut assign_id = |material: &mut Material| {
+            material.id = *next_id;
+            *next_id += 1;
+        };
+        assign_id(&mut fallback);
+        let mut library = HashMap::new();
+        for file in obj.material_libraries.iter() {
+            let library_path = base.join(file);
+            if !library_path.is_file() {
+                eprintln!("{}: material library {} not found, using the default material", path, library_path.display());
+                continue;
+            }
+            for (name, mut material) in load_mtl(&library_path)? {
+                assign_id(&mut material);
+                library.insert(name, material);
+            }
+        }
+        let mut face_materials: Vec<Option<&Material>> = vec![None; obj.polygons.len()];
+        for (name, group) in obj.meshes.iter() {
+            if let Some(material) = library.get(name) {
+                for range in group.polygons.iter() {
+                    let end = range.end.min(face_materials.len());
+                    face_materials[range.start.min(end)..end].fill(Some(material));
+                }
+            }
+        }
+        Ok(Model::from_obj(&obj, fallback, position, scale, |i| face_materials[i].cloned()))
+    }
+
+    // face_material overrides the material of the polygon with the given index
+    fn from_obj<F>(obj: &RawObj, material: Material, position: Point3<f64>, scale: Vector3<f64>, face_material: F) -> Model
+    where
+        F: Fn(usize) -> Option<Material>,
+    {
         let mut triangles = Vec::new();
         let translation = position.to_vec();
         let transform = Matrix4::from_translation(translation) * cgmath::Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z);
-        for i in (0..model.indices.len()-4).step_by(3) {
-            let v0 = vertex2point(model.vertices[model.indices[i] as usize]);
-            let v1 = vertex2point(model.vertices[model.indices[i+1] as usize]);
-            let v2 = vertex2point(model.vertices[model.indices[i+2] as usize]);
-            let n0 = vertex2normal(model.vertices[model.indices[i] as usize]);
-            let n1 = vertex2normal(model.vertices[model.indices[i+1] as usize]);
-            let n2 = vertex2normal(model.vertices[model.indices[i+2] as usize]);
-            triangles.push(Triangle::new(
-                transform.transform_point(v0), 
-                transform.transform_point(v1), 
-                transform.transform_point(v2),
-                (n0 + n1 + n2).normalize(),
-                material.clone()));
-            
+        // Normals transform with the inverse transpose, which for a scale is the reciprocal
+        // scale, so mirrored models get mirrored normals
+        let transform_normal = |n: Vector3<f64>| Vector3 {x: n.x / scale.x, y: n.y / scale.y, z: n.z / scale.z}.normalize();
+        // Mirroring also flips the winding of the faces
+        let mirrored = scale.x * scale.y * scale.z < 0.;
+        for (index, polygon) in obj.polygons.iter().enumerate() {
+            let polygon_material = face_material(index).unwrap_or_else(|| material.clone());
+            // Position, texture coordinate and normal indices of every corner
+            let corners: Vec<(usize, Option<usize>, Option<usize>)> = match polygon {
+                Polygon::P(corners) => corners.iter().map(|&p| (p, None, None)).collect(),
+                Polygon::PT(corners) => corners.iter().map(|&(p, t)| (p, Some(t), None)).collect(),
+                Polygon::PN(corners) => corners.iter().map(|&(p, n)| (p, None, Some(n))).collect(),
+                Polygon::PTN(corners) => corners.iter().map(|&(p, t, n)| (p, Some(t), Some(n))).collect(),
+            };
+            // Polygons with more corners are split into a fan of triangles
+            for i in 1..corners.len().saturating_sub(1) {
+                let corners = if mirrored {
+                    [corners[0], corners[i + 1], corners[i]]
+                } else {
+                    [corners[0], corners[i], corners[i + 1]]
+                };
+                let [v0, v1, v2] = corners.map(|(p, _, _)| transform.transform_point(obj_point(obj.positions[p])));
+                let uvs = corners.map(|(_, t, _)| t.map_or(Vector2 {x: 0., y: 0.}, |t| obj_uv(obj.tex_coords[t])));
+                // Faces missing any vertex normal fall back to flat shading
+                let normals = match corners.map(|(_, _, n)| n) {
+                    [Some(n0), Some(n1), Some(n2)] => Some([n0, n1, n2].map(|n| transform_normal(obj_normal(obj.normals[n])))),
+                    _ => None,
+                };
+                triangles.push(Triangle::new(v0, v1, v2, normals, uvs, polygon_material.clone()));
+            }
         }
-        println!("Model has {} triangles.", triangles.len());
-        println!("Building k-d tree with model's triangles...");
+        statusln!("Model has {} triangles.", triangles.len());
+        statusln!("Building k-d tree with model's triangles...");
         let triangles: Vec<Arc<Triangle>> = triangles.into_iter().map(Arc::new).collect();
         Model {
             material,
@@ -58,7 +127,9 @@ impl Model {
 
 impl Entity for Model {
     fn collide(&self, ray: &Ray) -> ColliderResult {
-        self.tree.collide(ray)
+        let mut result = self.tree.collide(ray);
+        result.object_position = result.position - self.position.to_vec();
+        result
     }
 
     fn material(&self) -> Option<&Material> {
@@ -83,21 +154,34 @@ impl Entity for Model {
             unsafe { (*triangle).translate(vec); }
         }
         self.tree.translate_nodes(vec);
+        self.position += vec;
     }
 }
 
-fn vertex2point(v: obj::Vertex) -> Point3<f64> {
+fn read_obj(path: &str) -> Result<RawObj> {
+    let file = File::open(path).with_context(|| format!("Could not open model {}", path))?;
+    parse_obj(BufReader::new(file)).with_context(|| format!("Could not parse model {}", path))
+}
+
+fn obj_point(p: (f32, f32, f32, f32)) -> Point3<f64> {
     Point3 {
-        x: v.position[0] as f64,
-        y: v.position[1] as f64,
-        z: v.position[2] as f64,
+        x: p.0 as f64,
+        y: p.1 as f64,
+        z: p.2 as f64,
     }
 }
 
-fn vertex2normal(v: obj::Vertex) -> Vector3<f64> {
+fn obj_normal(n: (f32, f32, f32)) -> Vector3<f64> {
     Vector3 {
-        x: v.normal[0] as f64,
-        y: v.normal[1] as f64,
-        z: v.normal[2] as f64,
+        x: n.0 as f64,
+        y: n.1 as f64,
+        z: n.2 as f64,
     }.normalize()
-}
\ No newline at end of file
+}
+
+fn obj_uv(t: (f32, f32, f32)) -> Vector2<f64> {
+    Vector2 {
+        x: t.0 as f64,
+        y: t.1 as f64,
+    }
+}
@@ -12,7 +12,7 @@ use std::{sync::{Arc}};
 #[macro_export]
 macro_rules! entity_enum {
     ($name:ident, $($x: ident),+) => {
-        use derive_entity::*;
+        use $crate::derive_entity::*;
         #[derive(Entity)]
         enum $name {
             $($x($x),)+
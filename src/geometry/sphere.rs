@@ -4,7 +4,8 @@ use crate::material::Material;
 use crate::geometry::aabb::AABB;
 use crate::common::{Entity, ColliderResult, Ray};
 
-use cgmath::{InnerSpace, Point3, Vector3};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2, Vector3};
+use std::f64::consts::PI;
 
 pub struct Sphere {
     position: Point3<f64>,
@@ -28,21 +29,33 @@ impl Entity for Sphere {
     fn collide(&self, ray: &Ray) -> ColliderResult {
         let l = self.position - ray.origin;
         let tca = l.dot(ray.direction);
-        if tca < 0.0 {
-            return ColliderResult::negative();
-        }
         let d2 = l.magnitude2() - tca.powi(2);
         if d2 > self.radius2 {
             return ColliderResult::negative();
         }
         let thc = (self.radius2 - d2).sqrt();
-        let pos = ray.origin + (tca - thc) * ray.direction;
+        // The near side, or the far side for rays starting inside like refracted ones. The
+        // normal points outwards either way.
+        let t = if tca - thc > 0.0 {
+            tca - thc
+        } else if tca + thc > 0.0 {
+            tca + thc
+        } else {
+            return ColliderResult::negative();
+        };
+        let pos = ray.origin + t * ray.direction;
+        let normal = InnerSpace::normalize(pos - self.position);
+        let (dpdu, dpdv) = spherical_derivatives(normal, self.radius);
 
         ColliderResult {
             collision: true,
             material: Some(self.material.clone()),
             position: pos,
-            normal: InnerSpace::normalize(pos - self.position),
+            object_position: Point3::from_vec(pos - self.position),
+            normal,
+            uv: spherical_uv(normal),
+            dpdu,
+            dpdv,
         }
     }
 
@@ -68,4 +81,29 @@ impl Entity for Sphere {
     fn translate(&mut self, vec: Vector3<f64>) {
         self.position += vec;
     }
-}
\ No newline at end of file
+}
+
+// Longitude and latitude of a point on the unit sphere, with v going from 0 at the bottom to
+// 1 at the top (-Y is up)
+fn spherical_uv(normal: Vector3<f64>) -> Vector2<f64> {
+    Vector2 {
+        x: 0.5 + normal.z.atan2(normal.x) / (2. * PI),
+        y: normal.y.clamp(-1., 1.).acos() / PI,
+    }
+}
+
+// Derivatives of the position along spherical_uv, u going around the Y axis and v from the
+// bottom to the top. The poles pick an arbitrary longitude.
+fn spherical_derivatives(normal: Vector3<f64>, radius: f64) -> (Vector3<f64>, Vector3<f64>) {
+    let cos_theta = normal.y.clamp(-1., 1.);
+    let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+    let (cos_phi, sin_phi) = if sin_theta > 0. {
+        (normal.x / sin_theta, normal.z / sin_theta)
+    } else {
+        (1., 0.)
+    };
+    (
+        Vector3 {x: -sin_theta * sin_phi, y: 0., z: sin_theta * cos_phi} * (2. * PI * radius),
+        Vector3 {x: cos_theta * cos_phi, y: -sin_theta, z: cos_theta * sin_phi} * (PI * radius),
+    )
+}
@@ -1,60 +1,107 @@
 extern crate cgmath;
 
 use crate::material::Material;
-use crate::common::{Entity, ColliderResult, Ray};
+use crate::common::{tangent_frame, Entity, ColliderResult, Ray};
 use crate::geometry::aabb::AABB;
 
-use cgmath::{Vector3, InnerSpace, Point3};
+use cgmath::{Vector2, Vector3, InnerSpace, Point3};
 
 #[derive(Clone)]
 pub struct Triangle {
     pub v0: Point3<f64>,
     pub v1: Point3<f64>,
     pub v2: Point3<f64>,
+    // Geometric normal of the face, on the side of the vertex normals when there are any
     pub normal: Vector3<f64>,
+    // Vertex normals of v0, v1 and v2, interpolated over the face for smooth shading
+    pub normals: Option<[Vector3<f64>; 3]>,
+    // Texture coordinates of v0, v1 and v2
+    pub uvs: [Vector2<f64>; 3],
+    // Derivatives of the position along the texture coordinates, constant over the face
+    pub dpdu: Vector3<f64>,
+    pub dpdv: Vector3<f64>,
     material: Material
 }
 
 impl Triangle {
-    pub fn new(v0: Point3<f64>, v1: Point3<f64>, v2: Point3<f64>, normal: Vector3<f64>, material: Material) -> Triangle {
-        Triangle { v0, v1, v2, normal, material }
+    // Without vertex normals the face is flat, with its normal on the side the corners go
+    // counterclockwise around
+    pub fn new(
+        v0: Point3<f64>,
+        v1: Point3<f64>,
+        v2: Point3<f64>,
+        normals: Option<[Vector3<f64>; 3]>,
+        uvs: [Vector2<f64>; 3],
+        material: Material,
+    ) -> Triangle {
+        let mut normal = (v1 - v0).cross(v2 - v0).normalize();
+        if let Some([n0, n1, n2]) = normals {
+            if normal.dot(n0 + n1 + n2) < 0.0 {
+                normal = -normal;
+            }
+        }
+        let (dpdu, dpdv) = uv_derivatives([v0, v1, v2], uvs, normal);
+        Triangle { v0, v1, v2, normal, normals, uvs, dpdu, dpdv, material }
     }
-}
-
-impl Entity for Triangle {
-    fn collide(&self, ray: &Ray) -> ColliderResult {
-
-        // Möller–Trumbore intersection algorithm
 
+    // Distance along the ray to the hit and its barycentric coordinates (u, v), the weights
+    // of v1 and v2 (Möller–Trumbore)
+    pub fn intersect(&self, ray: &Ray) -> Option<(f64, f64, f64)> {
         const EPSILON: f64 = 0.0000001;
         let edge1 = self.v1 - self.v0;
         let edge2 = self.v2 - self.v0;
         let h = ray.direction.cross(edge2);
         let a = edge1.dot(h);
         if a > -EPSILON && a < EPSILON {
-            return ColliderResult::negative();
+            return None;
         }
         let f = 1.0/a;
         let s = ray.origin - self.v0;
         let u = f * s.dot(h);
         if !(0.0..=1.0).contains(&u) {
-            return ColliderResult::negative();
+            return None;
         }
         let q = s.cross(edge1);
         let v = f * ray.direction.dot(q);
         if v < 0.0 || u + v > 1.0 {
-            return ColliderResult::negative();
+            return None;
         }
         let t = f * edge2.dot(q);
         if t > EPSILON {
-            return ColliderResult{
+            Some((t, u, v))
+        } else {
+            None
+        }
+    }
+
+    // Shading normal at the barycentric coordinates (u, v)
+    pub fn normal_at(&self, u: f64, v: f64) -> Vector3<f64> {
+        match self.normals {
+            Some([n0, n1, n2]) => {
+                let n = n0 * (1.0 - u - v) + n1 * u + n2 * v;
+                if n.magnitude2() > 0.0 { n.normalize() } else { self.normal }
+            }
+            None => self.normal,
+        }
+    }
+}
+
+impl Entity for Triangle {
+    fn collide(&self, ray: &Ray) -> ColliderResult {
+        match self.intersect(ray) {
+            Some((t, u, v)) => ColliderResult {
                 collision: true,
                 material: Some(self.material.clone()),
                 position: ray.origin + ray.direction * t,
-                normal: self.normal,
-            }
+                // Models replace it with the position relative to themselves
+                object_position: ray.origin + ray.direction * t,
+                normal: self.normal_at(u, v),
+                uv: self.uvs[0] * (1.0 - u - v) + self.uvs[1] * u + self.uvs[2] * v,
+                dpdu: self.dpdu,
+                dpdv: self.dpdv,
+            },
+            None => ColliderResult::negative(),
         }
-        ColliderResult::negative()
     }
 
     fn bounding_box(&self) -> AABB {
@@ -81,4 +128,19 @@ impl Entity for Triangle {
         self.v1 += vec;
         self.v2 += vec;
     }
-}
\ No newline at end of file
+}
+
+// Solves for the tangent frame that maps the texture coordinates onto the face. Faces
+// without usable texture coordinates get an arbitrary frame around the normal.
+fn uv_derivatives(vertices: [Point3<f64>; 3], uvs: [Vector2<f64>; 3], normal: Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+    let (dp1, dp2) = (vertices[1] - vertices[0], vertices[2] - vertices[0]);
+    let (duv1, duv2) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+    let determinant = duv1.x * duv2.y - duv1.y * duv2.x;
+    if determinant.abs() < 1e-12 {
+        return tangent_frame(normal);
+    }
+    (
+        (dp1 * duv2.y - dp2 * duv1.y) / determinant,
+        (dp2 * duv1.x - dp1 * duv2.x) / determinant,
+    )
+}
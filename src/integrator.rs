@@ -0,0 +1,220 @@
+extern crate cgmath;
+
+use crate::common::*;
+use crate::material::Material;
+use crate::sampling;
+use crate::tracer::RayTracer;
+
+use cgmath::{ElementWise, InnerSpace, Vector3};
+use std::str::FromStr;
+
+// How camera rays are turned into colors
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Integrator {
+    // Direct light plus mirror reflections through RayBehavior::compute, fast but without
+    // any light bouncing between diffuse surfaces. Uses World::ambient for the rest.
+    Whitted,
+    // Monte Carlo global illumination through the path tracing methods of RayBehavior.
+    // Needs more samples per pixel, and ignores World::ambient.
+    PathTracing(PathSettings),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PathSettings {
+    // Hard limit on the path length
+    pub max_bounces: u32,
+    // Bounces before Russian roulette may end paths that carry little light
+    pub roulette_bounces: u32,
+}
+
+impl PathSettings {
+    pub fn new(max_bounces: u32) -> PathSettings {
+        PathSettings { max_bounces, roulette_bounces: 3 }
+    }
+}
+
+impl Default for PathSettings {
+    fn default() -> Self {
+        PathSettings::new(16)
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "whitted" => Ok(Integrator::Whitted),
+            "path" => Ok(Integrator::PathTracing(PathSettings::default())),
+            _ => Err(format!("unknown integrator \"{}\", expected whitted or path", s)),
+        }
+    }
+}
+
+// Radiance arriving along the ray, estimated with one path. Light is gathered at every
+// bounce by sampling the lights (next event estimation) and by following the material, with
+// the two combined by multiple importance sampling where both can reach the same light.
+pub fn trace_path(tracer: &RayTracer, ray: &Ray, world: &World, settings: &PathSettings) -> Vector3<f64> {
+    let mut radiance = Vector3 {x: 0., y: 0., z: 0.};
+    let mut throughput = Vector3 {x: 1., y: 1., z: 1.};
+    let mut ray = Ray {origin: ray.origin, direction: ray.direction, bounce: ray.bounce};
+    // Camera rays and mirror bounces see the sky directly, other bounces reached it through
+    // a material sample with this density
+    let mut specular = true;
+    let mut scatter_pdf = 0.;
+
+    for bounce in 0..=settings.max_bounces {
+        let hit = match world.intersect(&ray) {
+            Some((_, hit)) => hit,
+            None => {
+                radiance += throughput.mul_element_wise(escaped(tracer, &ray, world, specular, scatter_pdf));
+                break;
+            }
+        };
+        let material = match hit.material.as_ref() {
+            Some(material) => material,
+            None => break,
+        };
+        let wo = -ray.direction;
+        if hit.normal.dot(wo) < 0. {
+            let distance = (hit.position - ray.origin).magnitude();
+            for behavior in material.shaders.iter() {
+                throughput = throughput.mul_element_wise(behavior.transmittance(distance));
+            }
+        }
+
+        radiance += throughput.mul_element_wise(sample_direct(world, &hit, wo));
+        if bounce == settings.max_bounces {
+            break;
+        }
+
+        // Pick one of the behaviors that can continue the path, in proportion to its mix
+        let total_mix: f64 = material.shaders.iter().map(|b| b.mix().max(0.)).sum();
+        if total_mix <= 0. {
+            break;
+        }
+        let mut pick = sampling::random() * total_mix;
+        let chosen = material.shaders.iter()
+            .find(|b| {
+                pick -= b.mix().max(0.);
+                pick < 0.
+            })
+            .unwrap_or_else(|| material.shaders.last().unwrap());
+        let pick_probability = chosen.mix().max(0.) / total_mix;
+        let sample = match chosen.sample(&hit, wo, sampling::random(), sampling::random()) {
+            Some(sample) => sample,
+            None => break,
+        };
+
+        if sample.specular {
+            throughput = throughput.mul_element_wise(sample.weight) / pick_probability;
+            scatter_pdf = 0.;
+        } else {
+            // Every non-specular behavior could have produced the direction, so the estimate
+            // uses the material as a whole
+            let wi = sample.direction;
+            let pdf = scatter_density(material, &hit, wo, wi, total_mix);
+            let cos = hit.normal.dot(wi).abs();
+            if pdf <= 0. {
+                break;
+            }
+            throughput = throughput.mul_element_wise(evaluate(material, &hit, wo, wi)) * (cos / pdf);
+            scatter_pdf = pdf;
+        }
+        specular = sample.specular;
+
+        if bounce >= settings.roulette_bounces {
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+            if sampling::random() >= survival {
+                break;
+            }
+            throughput /= survival;
+        }
+
+        ray = Ray {
+            origin: offset_origin(hit.position, hit.normal, sample.direction),
+            direction: sample.direction,
+            bounce: ray.bounce + 1,
+        };
+    }
+    radiance
+}
+
+// Light sampled from every light source at a hit, weighted against the chance of the
+// material sampling the same direction
+fn sample_direct(world: &World, hit: &ColliderResult, wo: Vector3<f64>) -> Vector3<f64> {
+    let material = hit.material.as_ref().unwrap();
+    let total_mix: f64 = material.shaders.iter().map(|b| b.mix().max(0.)).sum();
+    let mut result = Vector3 {x: 0., y: 0., z: 0.};
+    for light in world.light_sources.iter() {
+        let count = light.sample_count().max(1);
+        for i in 0..count {
+            let (u, v) = sampling::stratified(i, count);
+            let sample = light.sample(hit.position, u, v);
+            if sample.pdf <= 0. {
+                continue;
+            }
+            let wi = -sample.direction;
+            let f = evaluate(material, hit, wo, wi);
+            if f.x <= 0. && f.y <= 0. && f.z <= 0. {
+                continue;
+            }
+            let cos = facing(hit.normal, wo).dot(wi);
+            if cos <= 0. {
+                continue;
+            }
+            if world.occluded(offset_origin(hit.position, hit.normal, wi), wi, sample.distance - SURFACE_OFFSET) {
+                continue;
+            }
+            let light_pdf = count as f64 * sample.pdf;
+            let weight = match light.emitted(wi) {
+                Some(_) => power_heuristic(light_pdf, scatter_density(material, hit, wo, wi, total_mix)),
+                None => 1.,
+            };
+            result += f.mul_element_wise(sample.radiance) * (cos * weight / light_pdf);
+        }
+    }
+    result
+}
+
+// Light from the sky for a path leaving the scene
+fn escaped(tracer: &RayTracer, ray: &Ray, world: &World, specular: bool, scatter_pdf: f64) -> Vector3<f64> {
+    if specular {
+        return tracer.sky(ray, world);
+    }
+    // Lights that can be hit are also sampled directly, so share the credit
+    let mut result = Vector3 {x: 0., y: 0., z: 0.};
+    let mut sampled = false;
+    for light in world.light_sources.iter() {
+        if let Some((radiance, light_pdf)) = light.emitted(ray.direction) {
+            let light_pdf = light.sample_count().max(1) as f64 * light_pdf;
+            result += radiance * power_heuristic(scatter_pdf, light_pdf);
+            sampled = true;
+        }
+    }
+    if sampled {
+        result
+    } else {
+        tracer.sky(ray, world)
+    }
+}
+
+fn evaluate(material: &Material, hit: &ColliderResult, wo: Vector3<f64>, wi: Vector3<f64>) -> Vector3<f64> {
+    material.shaders.iter()
+        .fold(Vector3 {x: 0., y: 0., z: 0.}, |sum, b| sum + b.evaluate(hit, wo, wi))
+}
+
+// Density of the material's behaviors together picking wi, each chosen in proportion to its mix
+fn scatter_density(material: &Material, hit: &ColliderResult, wo: Vector3<f64>, wi: Vector3<f64>, total_mix: f64) -> f64 {
+    if total_mix <= 0. {
+        return 0.;
+    }
+    material.shaders.iter()
+        .map(|b| b.mix().max(0.) / total_mix * b.pdf(hit, wo, wi))
+        .sum()
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0. { a / (a + b) } else { 0. }
+}
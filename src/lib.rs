@@ -0,0 +1,56 @@
+//! Multi-threaded ray tracing engine.
+//!
+//! Scenes are built either in code (a `World` of entities, lights and a sky, plus a
+//! `RayTracer` holding the camera and `RenderSettings`) or loaded from a TOML description
+//! with `load_scene`. `RayTracer::render` returns a linear HDR `Framebuffer` that can be
+//! post-processed, composed or saved.
+
+extern crate cgmath;
+extern crate image;
+extern crate pbr;
+
+pub extern crate derive_entity;
+
+pub mod aov;
+pub mod common;
+pub mod camera;
+pub mod filter;
+pub mod film;
+pub mod framebuffer;
+pub mod tracer;
+pub mod material;
+pub mod mtl;
+pub mod behavior;
+pub mod bsdf;
+pub mod bump;
+pub mod geometry;
+pub mod integrator;
+pub mod lighting;
+pub mod loader;
+pub mod noise;
+pub mod output;
+pub mod procedural;
+pub mod sampling;
+pub mod scheduler;
+pub mod texture;
+pub mod tonemap;
+
+pub use crate::aov::{save_with_aovs, Aov, AovBuffers};
+pub use crate::bsdf::{Bsdf, PrincipledBsdf};
+pub use crate::bump::{BumpMap, NormalMap, NormalPerturbation};
+pub use crate::camera::Camera;
+pub use crate::common::{color_vec, vector3, ColliderResult, Entity, Ray, RayBehavior, World};
+pub use crate::framebuffer::Framebuffer;
+pub use crate::filter::{BoxFilter, GaussianFilter, MitchellFilter, PixelFilter, TentFilter};
+pub use crate::geometry::{model::Model, scene::Scene, sphere::Sphere};
+pub use crate::integrator::{Integrator, PathSettings};
+pub use crate::lighting::{
+    DirectionalLight, DiskLight, EnvironmentLight, EnvironmentMap, IesProfile, LightSample, LightSource, PointLight, RectangleLight, SphereLight, SpotLight,
+};
+pub use crate::loader::load_scene;
+pub use crate::material::Material;
+pub use crate::output::ExrPrecision;
+pub use crate::procedural::{Pattern, ProceduralTexture, TextureSpace};
+pub use crate::texture::{ImageTexture, Texture};
+pub use crate::tonemap::{OutputTransform, ToneMap};
+pub use crate::tracer::{ProgressiveSettings, RayTracer, RenderSettings};
@@ -0,0 +1,168 @@
+extern crate cgmath;
+
+use crate::common::tangent_frame;
+use crate::lighting::{LightSample, LightSource};
+use crate::sampling;
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use std::f64::consts::PI;
+
+// Area lights emit intensity * color as radiance from every point of their surface and are
+// integrated with several shadow rays per shading point, which gives soft shadows. Like the
+// other lights they only light the scene and don't show up in camera rays.
+
+// Picking a point uniformly over an area A, seen from distance d at an angle to the light's
+// normal, has a solid angle density of d² / (A * cos)
+fn area_sample(pos: Point3<f64>, position: Point3<f64>, light_normal: Vector3<f64>, area: f64, radiance: Vector3<f64>) -> LightSample {
+    let direction = pos - position;
+    let distance = direction.magnitude();
+    let direction = direction / distance;
+    let cos = light_normal.dot(direction);
+    LightSample {
+        position,
+        direction,
+        distance,
+        radiance,
+        // Points behind the light get nothing
+        pdf: if cos > 0. { distance * distance / (area * cos) } else { 0. },
+    }
+}
+
+// Parallelogram around position spanned by the edge vectors u and v, lit on the side of u × v
+pub struct RectangleLight {
+    position: Point3<f64>,
+    u: Vector3<f64>,
+    v: Vector3<f64>,
+    normal: Vector3<f64>,
+    area: f64,
+    color: Vector3<f64>,
+    intensity: f64,
+    samples: u32,
+}
+
+impl RectangleLight {
+    pub fn new(position: Point3<f64>, u: Vector3<f64>, v: Vector3<f64>, color: Vector3<f64>, intensity: f64, samples: u32) -> Self {
+        let cross = u.cross(v);
+        Self {
+            position,
+            u,
+            v,
+            normal: cross.normalize(),
+            area: cross.magnitude(),
+            color,
+            intensity,
+            samples,
+        }
+    }
+}
+
+impl LightSource for RectangleLight {
+    fn sample(&self, pos: Point3<f64>, u: f64, v: f64) -> LightSample {
+        let point = self.position + self.u * (u - 0.5) + self.v * (v - 0.5);
+        area_sample(pos, point, self.normal, self.area, self.color * self.intensity)
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.samples
+    }
+}
+
+// Disk around position facing normal, lit on that side
+pub struct DiskLight {
+    position: Point3<f64>,
+    normal: Vector3<f64>,
+    radius: f64,
+    color: Vector3<f64>,
+    intensity: f64,
+    samples: u32,
+}
+
+impl DiskLight {
+    pub fn new(position: Point3<f64>, normal: Vector3<f64>, radius: f64, color: Vector3<f64>, intensity: f64, samples: u32) -> Self {
+        Self {
+            position,
+            normal: normal.normalize(),
+            radius,
+            color,
+            intensity,
+            samples,
+        }
+    }
+}
+
+impl LightSource for DiskLight {
+    fn sample(&self, pos: Point3<f64>, u: f64, v: f64) -> LightSample {
+        let (tangent, bitangent) = tangent_frame(self.normal);
+        let (x, y) = sampling::concentric_disk(u, v);
+        let point = self.position + (tangent * x + bitangent * y) * self.radius;
+        area_sample(pos, point, self.normal, PI * self.radius * self.radius, self.color * self.intensity)
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.samples
+    }
+}
+
+// Glowing ball, lit all around
+pub struct SphereLight {
+    position: Point3<f64>,
+    radius: f64,
+    color: Vector3<f64>,
+    intensity: f64,
+    samples: u32,
+}
+
+impl SphereLight {
+    pub fn new(position: Point3<f64>, radius: f64, color: Vector3<f64>, intensity: f64, samples: u32) -> Self {
+        Self {
+            position,
+            radius,
+            color,
+            intensity,
+            samples,
+        }
+    }
+}
+
+impl LightSource for SphereLight {
+    // Only the cap facing pos can be seen, so directions are picked uniformly inside the cone
+    // it subtends instead of wasting samples on the back of the sphere
+    fn sample(&self, pos: Point3<f64>, u: f64, v: f64) -> LightSample {
+        let to_center = self.position - pos;
+        let distance2 = to_center.magnitude2();
+        let radius2 = self.radius * self.radius;
+        if distance2 <= radius2 {
+            return LightSample {
+                position: self.position,
+                direction: -to_center,
+                distance: 0.,
+                radiance: Vector3 {x: 0., y: 0., z: 0.},
+                pdf: 0.,
+            };
+        }
+        let distance = distance2.sqrt();
+        let axis = to_center / distance;
+
+        let cos_max = (1. - radius2 / distance2).sqrt();
+        let cos_theta = 1. - u * (1. - cos_max);
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * PI * v;
+        let (tangent, bitangent) = tangent_frame(axis);
+        let to_light = tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + axis * cos_theta;
+
+        // Nearest intersection with the sphere, grazing directions can miss by rounding
+        let b = distance * cos_theta;
+        let hit_distance = b - (radius2 - distance2 + b * b).max(0.).sqrt();
+        LightSample {
+            position: pos + to_light * hit_distance,
+            direction: -to_light,
+            distance: hit_distance,
+            radiance: self.color * self.intensity,
+            pdf: 1. / (2. * PI * (1. - cos_max)),
+        }
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.samples
+    }
+}
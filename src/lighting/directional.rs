@@ -0,0 +1,35 @@
+extern crate cgmath;
+
+use crate::lighting::{LightSample, LightSource};
+
+use cgmath::{InnerSpace, Point3, Vector3};
+
+// Parallel light from infinitely far away, like the sun
+pub struct DirectionalLight {
+    direction: Vector3<f64>,
+    color: Vector3<f64>,
+    intensity: f64,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vector3<f64>, color: Vector3<f64>, intensity: f64) -> Self {
+        let direction = direction.normalize();
+        Self {
+            direction,
+            color,
+            intensity,
+        }
+    }
+}
+
+impl LightSource for DirectionalLight {
+    fn sample(&self, pos: Point3<f64>, _u: f64, _v: f64) -> LightSample {
+        LightSample {
+            position: pos - self.direction,
+            direction: self.direction,
+            distance: f64::INFINITY,
+            radiance: self.color * self.intensity,
+            pdf: 1.,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+extern crate cgmath;
+
+use crate::lighting::{LightSample, LightSource};
+use crate::sampling::Distribution2D;
+
+use cgmath::{Point3, Vector3};
+use std::{f64::consts::PI, sync::Arc};
+
+// Light arriving from infinitely far away, like a sky cubemap or an HDR panorama
+pub trait EnvironmentMap: Sync + Send {
+    fn radiance(&self, direction: Vector3<f64>) -> Vector3<f64>;
+}
+
+// Resolution of the latitude-longitude grid the environment is importance sampled on
+const GRID_WIDTH: usize = 256;
+const GRID_HEIGHT: usize = 128;
+
+// Lights the scene with an environment map. Directions are picked in proportion to the
+// map's luminance, so the sun in a sunset sky gets most of the shadow rays while the rest of
+// the sky still adds its color to everything it can see.
+pub struct EnvironmentLight {
+    map: Arc<dyn EnvironmentMap>,
+    intensity: f64,
+    samples: u32,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentLight {
+    pub fn new(map: Arc<dyn EnvironmentMap>, intensity: f64, samples: u32) -> Self {
+        // Average of 2x2 directions per cell, weighted by the solid angle the cell covers
+        let mut values = Vec::with_capacity(GRID_WIDTH * GRID_HEIGHT);
+        for y in 0..GRID_HEIGHT {
+            for x in 0..GRID_WIDTH {
+                let mut luminance = 0.;
+                for (dx, dy) in [(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)] {
+                    let u = (x as f64 + dx) / GRID_WIDTH as f64;
+                    let v = (y as f64 + dy) / GRID_HEIGHT as f64;
+                    let radiance = map.radiance(lat_long_direction(u, v));
+                    luminance += 0.2126 * radiance.x + 0.7152 * radiance.y + 0.0722 * radiance.z;
+                }
+                let sin_theta = (PI * (y as f64 + 0.5) / GRID_HEIGHT as f64).sin();
+                values.push(luminance / 4. * sin_theta);
+            }
+        }
+        Self {
+            map,
+            intensity,
+            samples,
+            distribution: Distribution2D::new(&values, GRID_WIDTH, GRID_HEIGHT),
+        }
+    }
+}
+
+impl LightSource for EnvironmentLight {
+    fn sample(&self, pos: Point3<f64>, u: f64, v: f64) -> LightSample {
+        let ((x, y), pdf) = self.distribution.sample(u, v);
+        let to_light = lat_long_direction(x, y);
+        LightSample {
+            position: pos + to_light,
+            direction: -to_light,
+            distance: f64::INFINITY,
+            radiance: self.map.radiance(to_light) * self.intensity,
+            pdf: solid_angle_pdf(pdf, y),
+        }
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.samples
+    }
+
+    fn emitted(&self, direction: Vector3<f64>) -> Option<(Vector3<f64>, f64)> {
+        let (u, v) = direction_lat_long(direction);
+        Some((self.map.radiance(direction) * self.intensity, solid_angle_pdf(self.distribution.pdf(u, v), v)))
+    }
+}
+
+// The grid maps to the sphere with a density of 1 / (2π² sin θ)
+fn solid_angle_pdf(grid_pdf: f64, v: f64) -> f64 {
+    let sin_theta = (PI * v).sin();
+    if sin_theta > 0. { grid_pdf / (2. * PI * PI * sin_theta) } else { 0. }
+}
+
+// Direction for a point of a latitude-longitude map: u goes around the horizon, v goes from
+// straight up (-Y) at 0 to straight down at 1
+pub fn lat_long_direction(u: f64, v: f64) -> Vector3<f64> {
+    let phi = 2. * PI * u;
+    let theta = PI * v;
+    Vector3 {
+        x: theta.sin() * phi.cos(),
+        y: -theta.cos(),
+        z: theta.sin() * phi.sin(),
+    }
+}
+
+// Inverse of lat_long_direction, for a normalized direction
+pub fn direction_lat_long(direction: Vector3<f64>) -> (f64, f64) {
+    let u = direction.z.atan2(direction.x).rem_euclid(2. * PI) / (2. * PI);
+    let v = (-direction.y).clamp(-1., 1.).acos() / PI;
+    (u, v)
+}
@@ -0,0 +1,210 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::{fs, path::Path};
+
+// Photometric web from an IESNA LM-63 file: candela values over vertical angles (0 is
+// straight down the light's axis) and horizontal angles around it. Only type C photometry
+// is supported, which is what nearly every luminaire file uses.
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    // One row of vertical samples per horizontal angle, normalized so the peak is 1
+    candela: Vec<Vec<f64>>,
+}
+
+impl IesProfile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<IesProfile> {
+        let source = fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Could not read IES profile {}", path.as_ref().display()))?;
+        IesProfile::parse(&source)
+            .with_context(|| format!("Invalid IES profile {}", path.as_ref().display()))
+    }
+
+    pub fn parse(source: &str) -> Result<IesProfile> {
+        // Header and [KEYWORD] lines run up to the TILT line, numbers follow
+        let tilt_line = source.lines()
+            .position(|line| line.trim_start().starts_with("TILT="))
+            .ok_or_else(|| anyhow!("missing TILT line"))?;
+        let tilt = source.lines().nth(tilt_line).unwrap().trim()["TILT=".len()..].to_owned();
+        let mut numbers = source.lines()
+            .skip(tilt_line + 1)
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| token.parse::<f64>().map_err(|_| anyhow!("invalid number \"{}\"", token)));
+        let mut next = || numbers.next().unwrap_or_else(|| Err(anyhow!("file ends early")));
+
+        // Lamp tilt factors, which don't apply to a light that isn't tilted anyway
+        if tilt == "INCLUDE" {
+            next()?;
+            let pairs = next()? as usize;
+            for _ in 0..pairs * 2 {
+                next()?;
+            }
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        let photometric_type = next()?;
+        // Units, dimensions, ballast factor, future use and input watts
+        for _ in 0..7 {
+            next()?;
+        }
+        if photometric_type != 1. {
+            bail!("only type C photometry is supported");
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            bail!("no angles");
+        }
+
+        let vertical_angles = (0..vertical_count).map(|_| next()).collect::<Result<Vec<f64>>>()?;
+        let horizontal_angles = (0..horizontal_count).map(|_| next()).collect::<Result<Vec<f64>>>()?;
+        let mut candela = (0..horizontal_count)
+            .map(|_| (0..vertical_count).map(|_| Ok(next()? * multiplier)).collect::<Result<Vec<f64>>>())
+            .collect::<Result<Vec<Vec<f64>>>>()?;
+
+        let peak = candela.iter().flatten().cloned().fold(0., f64::max);
+        if peak <= 0. {
+            bail!("all candela values are zero");
+        }
+        for value in candela.iter_mut().flatten() {
+            *value /= peak;
+        }
+        Ok(IesProfile { vertical_angles, horizontal_angles, candela })
+    }
+
+    // Relative intensity in [0, 1] at vertical angle theta from the axis and horizontal angle
+    // phi around it, both in degrees
+    pub fn intensity(&self, theta: f64, phi: f64) -> f64 {
+        let (v0, v1, vt) = match bracket(&self.vertical_angles, theta) {
+            Some(b) => b,
+            None => return 0.,
+        };
+        let phi = self.fold_horizontal(phi);
+        let (h0, h1, ht) = bracket(&self.horizontal_angles, phi).unwrap_or((0, 0, 0.));
+        let row = |h: usize| self.candela[h][v0] * (1. - vt) + self.candela[h][v1] * vt;
+        row(h0) * (1. - ht) + row(h1) * ht
+    }
+
+    // Files only store the part of the distribution that isn't symmetric, the last horizontal
+    // angle says which: 0 for rotationally symmetric, 90 for quadrants, 180 for two halves
+    fn fold_horizontal(&self, phi: f64) -> f64 {
+        let phi = phi.rem_euclid(360.);
+        let last = *self.horizontal_angles.last().unwrap();
+        if last <= 0. {
+            0.
+        } else if last <= 90. {
+            let phi = if phi > 180. { 360. - phi } else { phi };
+            if phi > 90. { 180. - phi } else { phi }
+        } else if last <= 180. {
+            if phi > 180. { 360. - phi } else { phi }
+        } else {
+            phi
+        }
+    }
+}
+
+// Indices of the sorted angles around x and how far x is between them, None outside the range
+fn bracket(angles: &[f64], x: f64) -> Option<(usize, usize, f64)> {
+    let first = angles[0];
+    let last = *angles.last().unwrap();
+    if x < first || x > last {
+        return None;
+    }
+    if angles.len() == 1 {
+        return Some((0, 0, 0.));
+    }
+    let i = angles.windows(2).position(|w| x <= w[1]).unwrap_or(angles.len() - 2);
+    let span = angles[i + 1] - angles[i];
+    let t = if span > 0. { (x - angles[i]) / span } else { 0. };
+    Some((i, i + 1, t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rotationally symmetric: full intensity down the axis, half at 45 degrees, dark past 90
+    const SYMMETRIC: &str = "IESNA:LM-63-2002
+[TEST] symmetric
+[MANUFAC] nobody
+TILT=NONE
+1 1000 2 5 1 1 2 0.5 0.5 0.0
+1.0 1.0 100.0
+0 45 90 135 180
+0
+100 50 0 0 0
+";
+
+    // Two halves around the axis, the one at 0 twice as bright as the one at 180, with values
+    // separated by commas over several lines
+    const HALVES: &str = "IESNA91
+TILT=NONE
+1 -1 1
+3 3 1 1 0 0 0
+1 1 50
+0, 45, 90
+0, 90,
+180
+200, 100, 0
+100, 50, 0
+100, 50, 0
+";
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn parses_and_normalizes_candela() {
+        let profile = IesProfile::parse(SYMMETRIC).unwrap();
+        assert_eq!(profile.vertical_angles, vec![0., 45., 90., 135., 180.]);
+        assert_eq!(profile.horizontal_angles, vec![0.]);
+        assert_eq!(profile.candela, vec![vec![1., 0.5, 0., 0., 0.]]);
+    }
+
+    #[test]
+    fn interpolates_vertical_angles() {
+        let profile = IesProfile::parse(SYMMETRIC).unwrap();
+        assert!(close(profile.intensity(0., 0.), 1.));
+        assert!(close(profile.intensity(22.5, 0.), 0.75));
+        assert!(close(profile.intensity(45., 123.), 0.5));
+        assert!(close(profile.intensity(120., 0.), 0.));
+    }
+
+    #[test]
+    fn angles_outside_the_file_are_dark() {
+        let profile = IesProfile::parse(HALVES).unwrap();
+        assert_eq!(profile.intensity(100., 0.), 0.);
+        assert_eq!(profile.intensity(-1., 0.), 0.);
+    }
+
+    #[test]
+    fn mirrors_missing_horizontal_angles() {
+        let profile = IesProfile::parse(HALVES).unwrap();
+        assert!(close(profile.intensity(0., 0.), 1.));
+        assert!(close(profile.intensity(0., 180.), 0.5));
+        assert!(close(profile.intensity(0., 45.), 0.75));
+        // The other half mirrors the stored one
+        assert!(close(profile.intensity(45., 315.), profile.intensity(45., 45.)));
+        assert!(close(profile.intensity(45., -90.), profile.intensity(45., 90.)));
+    }
+
+    #[test]
+    fn skips_tilt_tables() {
+        let tilted = SYMMETRIC.replace("TILT=NONE\n", "TILT=INCLUDE\n1\n3\n0 45 90\n1 0.9 0.8\n");
+        let profile = IesProfile::parse(&tilted).unwrap();
+        assert_eq!(profile.vertical_angles.len(), 5);
+        assert!(close(profile.intensity(45., 0.), 0.5));
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(IesProfile::parse("IESNA:LM-63-2002\n1 2 3\n").is_err());
+        assert!(IesProfile::parse(&SYMMETRIC.replace("100 50 0 0 0", "100 50 0 0")).is_err());
+        assert!(IesProfile::parse(&SYMMETRIC.replace("100 50", "100 fifty")).is_err());
+        assert!(IesProfile::parse(&SYMMETRIC.replace("5 1 1 2", "5 1 2 2")).is_err());
+        assert!(IesProfile::parse(&SYMMETRIC.replace("100 50", "0 0")).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+extern crate cgmath;
+
+pub mod area;
+pub mod directional;
+pub mod environment;
+pub mod ies;
+pub mod point;
+pub mod spot;
+
+pub use self::area::{DiskLight, RectangleLight, SphereLight};
+pub use self::directional::DirectionalLight;
+pub use self::environment::{EnvironmentLight, EnvironmentMap};
+pub use self::ies::IesProfile;
+pub use self::point::PointLight;
+pub use self::spot::SpotLight;
+
+use crate::common::*;
+use crate::sampling;
+
+use cgmath::{InnerSpace, Point3, Vector3};
+
+// One point picked on a light, as seen from a shading point
+pub struct LightSample {
+    // Point on the light. Directional lights have none, theirs is one unit towards the light.
+    pub position: Point3<f64>,
+    // Normalized, pointing from the light towards the shading point
+    pub direction: Vector3<f64>,
+    // To the light, infinite for directional lights
+    pub distance: f64,
+    // Light arriving along direction: the light's color times its power
+    pub radiance: Vector3<f64>,
+    // Density of picking this sample, per solid angle for area lights and 1 for lights
+    // that can only be reached one way (point, directional)
+    pub pdf: f64,
+}
+
+pub trait LightSource: Sync + Send {
+    // Picks a point on the light for pos, u and v are uniform in [0, 1)
+    fn sample(&self, pos: Point3<f64>, u: f64, v: f64) -> LightSample;
+
+    // Shadow rays per shading point, more of them give smoother penumbrae
+    fn sample_count(&self) -> u32 {
+        1
+    }
+
+    // Lights that paths can run into (the environment) return the radiance arriving from
+    // direction, which points away from the scene, along with the density sample picks it with
+    fn emitted(&self, _direction: Vector3<f64>) -> Option<(Vector3<f64>, f64)> {
+        None
+    }
+}
+
+// Calls shade with every light sample that reaches pos from above the surface unoccluded,
+// along with the weight turning the sum into an estimate of the light arriving: radiance *
+// weight is the light from the sample's direction, averaged over each light's samples.
+pub fn sample_lights<F>(world: &World, pos: Point3<f64>, normal: Vector3<f64>, mut shade: F)
+where
+    F: FnMut(&LightSample, f64),
+{
+    for light_source in world.light_sources.iter() {
+        sample_light(world, light_source.as_ref(), pos, normal, &mut shade);
+    }
+}
+
+// sample_lights for a single light
+pub fn sample_light<F>(world: &World, light_source: &dyn LightSource, pos: Point3<f64>, normal: Vector3<f64>, mut shade: F)
+where
+    F: FnMut(&LightSample, f64),
+{
+    let count = light_source.sample_count().max(1);
+    for i in 0..count {
+        let (u, v) = sampling::stratified(i, count);
+        let sample = light_source.sample(pos, u, v);
+        if sample.pdf <= 0. || normal.dot(sample.direction) >= 0. {
+            continue;
+        }
+        let to_light = -sample.direction;
+        let origin = offset_origin(pos, normal, to_light);
+        if world.occluded(origin, to_light, sample.distance - SURFACE_OFFSET) {
+            continue;
+        }
+        shade(&sample, 1. / (sample.pdf * count as f64));
+    }
+}
@@ -0,0 +1,38 @@
+extern crate cgmath;
+
+use crate::lighting::{LightSample, LightSource};
+
+use cgmath::{InnerSpace, Point3, Vector3};
+
+pub struct PointLight {
+    position: Point3<f64>,
+    color: Vector3<f64>,
+    brightness: f64,
+    attenuation: f64,
+}
+
+impl PointLight {
+    // Power falls off as brightness / (attenuation * distance²)
+    pub fn new(position: Point3<f64>, color: Vector3<f64>, brightness: f64, attenuation: f64) -> Self {
+        Self {
+            position,
+            color,
+            brightness,
+            attenuation,
+        }
+    }
+}
+
+impl LightSource for PointLight {
+    fn sample(&self, pos: Point3<f64>, _u: f64, _v: f64) -> LightSample {
+        let direction = pos - self.position;
+        let distance2 = direction.magnitude2();
+        LightSample {
+            position: self.position,
+            direction: direction.normalize(),
+            distance: distance2.sqrt(),
+            radiance: self.color * (self.brightness / (self.attenuation * distance2)),
+            pdf: 1.,
+        }
+    }
+}
@@ -0,0 +1,91 @@
+extern crate cgmath;
+
+use crate::common::tangent_frame;
+use crate::lighting::{ies::IesProfile, LightSample, LightSource};
+
+use cgmath::{Deg, InnerSpace, Point3, Rad, Vector3};
+
+// Point light shining into a cone: full power inside the inner angle, fading smoothly to
+// nothing at the outer angle. An IES profile further shapes the light inside the cone.
+pub struct SpotLight {
+    position: Point3<f64>,
+    direction: Vector3<f64>,
+    cos_inner: f64,
+    cos_outer: f64,
+    color: Vector3<f64>,
+    brightness: f64,
+    attenuation: f64,
+    profile: Option<IesProfile>,
+}
+
+impl SpotLight {
+    // Angles are measured from the direction to the edge of the cone, the inner one can't be
+    // larger. Power falls off with distance like a point light's.
+    pub fn new(
+        position: Point3<f64>,
+        direction: Vector3<f64>,
+        inner_angle: Deg<f64>,
+        outer_angle: Deg<f64>,
+        color: Vector3<f64>,
+        brightness: f64,
+        attenuation: f64,
+    ) -> Self {
+        assert!(
+            inner_angle <= outer_angle,
+            "inner_angle ({:?}) is larger than outer_angle ({:?})",
+            inner_angle,
+            outer_angle,
+        );
+        Self {
+            position,
+            direction: direction.normalize(),
+            cos_inner: Rad::from(inner_angle).0.cos(),
+            cos_outer: Rad::from(outer_angle).0.cos(),
+            color,
+            brightness,
+            attenuation,
+            profile: None,
+        }
+    }
+
+    // The profile's nadir (vertical angle 0) points along the light's direction
+    pub fn set_profile(&mut self, profile: IesProfile) {
+        self.profile = Some(profile);
+    }
+
+    fn falloff(&self, cos: f64) -> f64 {
+        if cos >= self.cos_inner {
+            return 1.;
+        }
+        if cos <= self.cos_outer {
+            return 0.;
+        }
+        let t = (cos - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3. - 2. * t)
+    }
+}
+
+impl LightSource for SpotLight {
+    fn sample(&self, pos: Point3<f64>, _u: f64, _v: f64) -> LightSample {
+        let direction = pos - self.position;
+        let distance2 = direction.magnitude2();
+        let direction = direction.normalize();
+
+        let cos = self.direction.dot(direction);
+        let mut power = self.brightness * self.falloff(cos) / (self.attenuation * distance2);
+        if let Some(profile) = &self.profile {
+            let (tangent, bitangent) = tangent_frame(self.direction);
+            let theta = Deg::from(Rad(cos.clamp(-1., 1.).acos())).0;
+            let phi = Deg::from(Rad(direction.dot(bitangent).atan2(direction.dot(tangent)))).0;
+            power *= profile.intensity(theta, phi);
+        }
+
+        LightSample {
+            position: self.position,
+            direction,
+            distance: distance2.sqrt(),
+            radiance: self.color * power,
+            pdf: 1.,
+        }
+    }
+}
@@ -0,0 +1,766 @@
+extern crate cgmath;
+
+use crate::common::*;
+use crate::camera::Camera;
+use crate::filter::*;
+use crate::geometry::{model::Model, sphere::Sphere};
+use crate::lighting::*;
+use crate::material::Material;
+use crate::behavior::{cubemap::CubemapBehavior, equirect::EquirectBehavior};
+use crate::behavior::reflection::{ReflectionBehavior, DEFAULT_GLOSSY_SAMPLES};
+use crate::aov::Aov;
+use crate::behavior::principled::PrincipledBehavior;
+use crate::bsdf::PrincipledBsdf;
+use crate::bump::{BumpMap, NormalMap, NormalPerturbation};
+use crate::integrator::{Integrator, PathSettings};
+use crate::mtl::default_material;
+use crate::output::ExrPrecision;
+use crate::procedural::{Checker, Fbm, Gradient, Marble, Noise, Pattern, ProceduralTexture, TextureSpace, Turbulence, Wood, Worley};
+use crate::texture::{ImageTexture, Texture};
+use crate::tonemap::ToneMap;
+use crate::tracer::{ProgressiveSettings, RayTracer, RenderSettings};
+
+use anyhow::{anyhow, bail, Context, Result};
+use cgmath::{Deg, InnerSpace, Point3, Vector3};
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, sync::Arc, time::Duration};
+use toml::Spanned;
+
+// Scene files are TOML. Paths inside them are relative to the scene file, and colors are
+// 0-255 RGB triples like color_vec. See scenes/ufo.toml for a complete example.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    render: RenderSection,
+    camera: CameraSection,
+    world: WorldSection,
+    #[serde(default)]
+    lights: Vec<Spanned<LightSection>>,
+    #[serde(default)]
+    textures: HashMap<String, TextureSection>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialSection>,
+    #[serde(default)]
+    spheres: Vec<SphereSection>,
+    #[serde(default)]
+    models: Vec<ModelSection>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RenderSection {
+    width: Spanned<u32>,
+    height: Spanned<u32>,
+    samples_per_pixel: Option<u32>,
+    filter: Option<FilterSection>,
+    threads: Option<usize>,
+    tile_size: Option<u32>,
+    progressive: Option<ProgressiveSection>,
+    seed: Option<u64>,
+    // In stops
+    exposure: Option<f64>,
+    tone_map: Option<ToneMapSection>,
+    exr_precision: Option<ExrPrecisionSection>,
+    aovs: Option<Vec<AovSection>>,
+    integrator: Option<IntegratorSection>,
+    // Path length limit of the path integrator
+    max_bounces: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FilterSection {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ToneMapSection {
+    Clamp,
+    Reinhard,
+    Aces,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ExrPrecisionSection {
+    Half,
+    Float,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AovSection {
+    Depth,
+    Normal,
+    Albedo,
+    Position,
+    EntityId,
+    MaterialId,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum IntegratorSection {
+    Whitted,
+    Path,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProgressiveSection {
+    samples_per_pass: u32,
+    // Seconds
+    snapshot_interval: Spanned<f64>,
+    time_budget: Option<Spanned<f64>>,
+    snapshot_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraSection {
+    eye: [f64; 3],
+    target: Spanned<[f64; 3]>,
+    up: Spanned<[f64; 3]>,
+    // Vertical, in degrees
+    fov: Spanned<f64>,
+    aperture: Option<f64>,
+    focus_distance: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorldSection {
+    ambient: f64,
+    sky: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum LightSection {
+    Directional {
+        direction: [f64; 3],
+        color: [u8; 3],
+        intensity: f64,
+    },
+    Point {
+        position: [f64; 3],
+        color: [u8; 3],
+        brightness: f64,
+        attenuation: f64,
+    },
+    // Area lights, samples is the number of shadow rays per shading point
+    Rectangle {
+        position: [f64; 3],
+        // Edge vectors, the light shines on the side of u × v
+        u: [f64; 3],
+        v: [f64; 3],
+        color: [u8; 3],
+        intensity: f64,
+        samples: Option<u32>,
+    },
+    Disk {
+        position: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        color: [u8; 3],
+        intensity: f64,
+        samples: Option<u32>,
+    },
+    Sphere {
+        position: [f64; 3],
+        radius: f64,
+        color: [u8; 3],
+        intensity: f64,
+        samples: Option<u32>,
+    },
+    // Angles in degrees from the direction to the edges of the cone, ies is the path of an
+    // optional photometric profile
+    Spot {
+        position: [f64; 3],
+        direction: [f64; 3],
+        inner_angle: f64,
+        outer_angle: f64,
+        color: [u8; 3],
+        brightness: f64,
+        attenuation: f64,
+        ies: Option<String>,
+    },
+    // Lights the scene with the world's sky
+    Environment {
+        intensity: Option<f64>,
+        samples: Option<u32>,
+    },
+}
+
+const DEFAULT_AREA_LIGHT_SAMPLES: u32 = 16;
+
+// Procedural textures, referenced by name wherever materials take a texture. Every type takes
+// space, scale (the size of the pattern in world units) and colors (at the pattern's 0 and 1).
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum TextureSection {
+    Checker {
+        space: Option<TextureSpaceSection>,
+        scale: Option<f64>,
+        colors: Option<[[u8; 3]; 2]>,
+    },
+    Gradient {
+        direction: Option<[f64; 3]>,
+        space: Option<TextureSpaceSection>,
+        scale: Option<f64>,
+        colors: Option<[[u8; 3]; 2]>,
+    },
+    Noise {
+        space: Option<TextureSpaceSection>,
+        scale: Option<f64>,
+        colors: Option<[[u8; 3]; 2]>,
+    },
+    Fbm {
+        octaves: Option<u32>,
+        gain: Option<f64>,
+        space: Option<TextureSpaceSection>,
+        scale: Option<f64>,
+        colors: Option<[[u8; 3]; 2]>,
+    },
+    Turbulence {
+        octaves: Option<u32>,
+        gain: Option<f64>,
+        space: Option<TextureSpaceSection>,
+        scale: Option<f64>,
+        colors: Option<[[u8; 3]; 2]>,
+    },
+    Marble {
+        direction: Option<[f64; 3]>,
+        turbulence: Option<f64>,
+        octaves: Option<u32>,
+        space: Option<TextureSpaceSection>,
+        scale: Option<f64>,
+        colors: Option<[[u8; 3]; 2]>,
+    },
+    Wood {
+        turbulence: Option<f64>,
+        space: Option<TextureSpaceSection>,
+        scale: Option<f64>,
+        colors: Option<[[u8; 3]; 2]>,
+    },
+    Worley {
+        space: Option<TextureSpaceSection>,
+        scale: Option<f64>,
+        colors: Option<[[u8; 3]; 2]>,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum TextureSpaceSection {
+    Object,
+    World,
+}
+
+const DEFAULT_OCTAVES: u32 = 5;
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum MaterialSection {
+    Lambert {
+        color: [u8; 3],
+        albedo: f64,
+        lambert: f64,
+        reflective: f64,
+        phong: f64,
+        alpha: i32,
+        // Blurs the reflection, 0 is a perfect mirror
+        roughness: Option<f64>,
+        reflection_samples: Option<u32>,
+        // Named texture, or image multiplying the color over the texture coordinates
+        texture: Option<String>,
+        // Tangent space normal map, or grayscale bump map raising white by bump_height
+        normal_map: Option<String>,
+        normal_strength: Option<f64>,
+        bump_map: Option<String>,
+        bump_height: Option<f64>,
+    },
+    Principled {
+        color: [u8; 3],
+        metallic: Option<f64>,
+        roughness: Option<f64>,
+        specular: Option<f64>,
+        ior: Option<f64>,
+        texture: Option<String>,
+        // Named textures or grayscale images multiplying metallic and roughness
+        metallic_texture: Option<String>,
+        roughness_texture: Option<String>,
+        normal_map: Option<String>,
+        normal_strength: Option<f64>,
+        bump_map: Option<String>,
+        bump_height: Option<f64>,
+    },
+    Dielectric {
+        ior: Option<f64>,
+        color: Option<[u8; 3]>,
+        absorption_distance: Option<f64>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereSection {
+    position: [f64; 3],
+    radius: f64,
+    material: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelSection {
+    path: Spanned<String>,
+    position: [f64; 3],
+    scale: Option<[f64; 3]>,
+    // Without one, the materials come from the MTL files of the model
+    material: Option<Spanned<String>>,
+}
+
+pub fn load_scene(path: &str) -> Result<(RayTracer, World)> {
+    let source = fs::read_to_string(path)
+        .with_context(|| format!("Could not read scene file {}", path))?;
+    let scene: SceneFile = toml::from_str(&source)
+        .map_err(|e| anyhow!("{}: {}", path, e))?;
+    let base = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+    let loader = Loader { path, source: &source, base };
+    loader.build(scene)
+}
+
+struct Loader<'a> {
+    path: &'a str,
+    source: &'a str,
+    base: &'a Path,
+}
+
+impl<'a> Loader<'a> {
+    fn build(&self, scene: SceneFile) -> Result<(RayTracer, World)> {
+        let settings = self.render_settings(&scene.render)?;
+        let camera = self.camera(&scene.camera, &settings)?;
+        let raytracer = RayTracer::new(settings, camera);
+
+        let (sky, environment) = self.sky(&scene.world.sky)?;
+        let light_sources = scene.lights.iter()
+            .map(|light| self.light(light, &environment))
+            .collect::<Result<Vec<Box<dyn LightSource>>>>()?;
+
+        let textures: HashMap<&str, Arc<dyn Texture>> = scene.textures.iter()
+            .map(|(name, texture)| Ok((name.as_str(), self.build_texture(name, texture)?)))
+            .collect::<Result<_>>()?;
+        // Material ids follow the names, so the material id AOV is the same on every run
+        let mut names: Vec<&String> = scene.materials.keys().collect();
+        names.sort();
+        let mut next_id = 1;
+        let mut materials: HashMap<&str, Material> = HashMap::new();
+        for name in names {
+            let mut material = self.build_material(&textures, name, &scene.materials[name])?;
+            material.id = next_id;
+            next_id += 1;
+            materials.insert(name.as_str(), material);
+        }
+
+        let mut entities: Vec<Box<dyn Entity>> = Vec::new();
+        for model in scene.models.iter() {
+            let file = self.resolve(&model.path)?;
+            let scale = model.scale.map(vector).unwrap_or(Vector3 {x: 1., y: 1., z: 1.});
+            let entity = match &model.material {
+                Some(material) => {
+                    let material = self.material(&materials, material)?;
+                    Model::new(&file.to_string_lossy(), material, point(model.position), scale)
+                        .with_context(|| self.error_at(&model.path, "could not load the model"))?
+                }
+                None => Model::new_with_mtl(&file.to_string_lossy(), default_material(), point(model.position), scale, &mut next_id)
+                    .with_context(|| self.error_at(&model.path, "could not load the model or its materials"))?,
+            };
+            entities.push(Box::new(entity));
+        }
+        for sphere in scene.spheres.iter() {
+            let material = self.material(&materials, &sphere.material)?;
+            entities.push(Box::new(Sphere::new(point(sphere.position), sphere.radius, material)));
+        }
+
+        let world = World {
+            entities,
+            light_sources,
+            sky,
+            ambient: scene.world.ambient,
+        };
+        Ok((raytracer, world))
+    }
+
+    fn render_settings(&self, render: &RenderSection) -> Result<RenderSettings> {
+        for size in [&render.width, &render.height] {
+            if *size.get_ref() == 0 {
+                bail!(self.error_at(size, "the image needs to be at least 1 pixel wide and high"));
+            }
+        }
+        let mut settings = RenderSettings::new((*render.width.get_ref(), *render.height.get_ref()));
+        if let Some(samples) = render.samples_per_pixel {
+            settings.samples_per_pixel = samples;
+        }
+        if let Some(filter) = &render.filter {
+            settings.filter = match filter {
+                FilterSection::Box => Box::new(BoxFilter::default()),
+                FilterSection::Tent => Box::new(TentFilter::default()),
+                FilterSection::Gaussian => Box::new(GaussianFilter::default()),
+                FilterSection::Mitchell => Box::new(MitchellFilter::default()),
+            };
+        }
+        if let Some(threads) = render.threads {
+            settings.threads = threads;
+        }
+        if let Some(tile_size) = render.tile_size {
+            settings.tile_size = tile_size;
+        }
+        if let Some(seed) = render.seed {
+            settings.seed = seed;
+        }
+        if let Some(exposure) = render.exposure {
+            settings.output.exposure = exposure;
+        }
+        if let Some(tone_map) = &render.tone_map {
+            settings.output.tone_map = match tone_map {
+                ToneMapSection::Clamp => ToneMap::Clamp,
+                ToneMapSection::Reinhard => ToneMap::Reinhard,
+                ToneMapSection::Aces => ToneMap::Aces,
+            };
+        }
+        if let Some(precision) = &render.exr_precision {
+            settings.output.exr_precision = match precision {
+                ExrPrecisionSection::Half => ExrPrecision::Half,
+                ExrPrecisionSection::Float => ExrPrecision::Float,
+            };
+        }
+        if let Some(aovs) = &render.aovs {
+            let aovs: Vec<Aov> = aovs.iter()
+                .map(|aov| match aov {
+                    AovSection::Depth => Aov::Depth,
+                    AovSection::Normal => Aov::Normal,
+                    AovSection::Albedo => Aov::Albedo,
+                    AovSection::Position => Aov::Position,
+                    AovSection::EntityId => Aov::EntityId,
+                    AovSection::MaterialId => Aov::MaterialId,
+                })
+                .collect();
+            settings.aovs = Aov::unique(&aovs);
+        }
+        settings.integrator = match render.integrator {
+            Some(IntegratorSection::Path) => {
+                let mut path = PathSettings::default();
+                if let Some(max_bounces) = render.max_bounces {
+                    path.max_bounces = max_bounces;
+                }
+                Integrator::PathTracing(path)
+            }
+            Some(IntegratorSection::Whitted) | None => Integrator::Whitted,
+        };
+        if let Some(progressive) = &render.progressive {
+            let mut p = ProgressiveSettings::new(
+                progressive.samples_per_pass,
+                self.seconds(&progressive.snapshot_interval)?,
+            );
+            p.time_budget = progressive.time_budget.as_ref().map(|t| self.seconds(t)).transpose()?;
+            p.snapshot_path = progressive.snapshot_path.clone();
+            settings.progressive = Some(p);
+        }
+        Ok(settings)
+    }
+
+    fn seconds(&self, value: &Spanned<f64>) -> Result<Duration> {
+        let seconds = *value.get_ref();
+        if !seconds.is_finite() || seconds < 0. {
+            bail!(self.error_at(value, "expected a duration of 0 seconds or more"));
+        }
+        Ok(Duration::from_secs_f64(seconds))
+    }
+
+    fn camera(&self, section: &CameraSection, settings: &RenderSettings) -> Result<Camera> {
+        let (eye, target, up) = (point(section.eye), point(*section.target.get_ref()), vector(*section.up.get_ref()));
+        if eye == target {
+            bail!(self.error_at(&section.target, "the camera can't look at its own eye position"));
+        }
+        if up.magnitude2() == 0. {
+            bail!(self.error_at(&section.up, "the up vector can't be zero"));
+        }
+        let fov = *section.fov.get_ref();
+        if !(fov > 0. && fov < 180.) {
+            bail!(self.error_at(&section.fov, "expected a field of view between 0 and 180 degrees"));
+        }
+        let aspect = settings.image_size.0 as f64 / settings.image_size.1 as f64;
+        let mut camera = Camera::new(eye, target, up, Deg(fov), aspect);
+        if let Some(aperture) = section.aperture {
+            let focus_distance = section.focus_distance.unwrap_or_else(|| camera.focus_distance());
+            camera.set_lens(aperture, focus_distance);
+        }
+        Ok(camera)
+    }
+
+    // A cubemap folder, or a single equirectangular image
+    fn sky(&self, path: &Spanned<String>) -> Result<(Material, Arc<dyn EnvironmentMap>)> {
+        let resolved = self.resolve(path)?;
+        if resolved.is_file() {
+            let sky = EquirectBehavior::load(&resolved, 1.0)
+                .map_err(|e| anyhow!(self.error_at(path, &format!("{:#}", e))))?;
+            let sky = Arc::new(sky);
+            return Ok((Material::new_sky_material_from(sky.clone()), sky));
+        }
+        for face in &["left", "right", "front", "back", "up", "down"] {
+            if !resolved.join(format!("{}.png", face)).is_file() {
+                bail!(self.error_at(path, &format!("cubemap folder has no {}.png", face)));
+            }
+        }
+        let sky = CubemapBehavior::new(&resolved.to_string_lossy(), 1.0)
+            .map_err(|e| anyhow!(self.error_at(path, &format!("{:#}", e))))?;
+        let sky = Arc::new(sky);
+        Ok((Material::new_sky_material_from(sky.clone()), sky))
+    }
+
+    // Spans aren't available inside tagged tables, so errors point at the whole light
+    fn light(&self, light: &Spanned<LightSection>, environment: &Arc<dyn EnvironmentMap>) -> Result<Box<dyn LightSource>> {
+        let direction_is_zero = |direction: [f64; 3]| vector(direction).magnitude2() == 0.;
+        let attenuation_is_invalid = |attenuation: f64| !(attenuation > 0. && attenuation.is_finite());
+        Ok(match *light.get_ref() {
+            LightSection::Directional { direction, color, intensity } => {
+                if direction_is_zero(direction) {
+                    bail!(self.error_at(light, "the light's direction can't be zero"));
+                }
+                Box::new(DirectionalLight::new(vector(direction), rgb(color), intensity))
+            }
+            LightSection::Point { position, color, brightness, attenuation } => {
+                if attenuation_is_invalid(attenuation) {
+                    bail!(self.error_at(light, "attenuation has to be more than 0"));
+                }
+                Box::new(PointLight::new(point(position), rgb(color), brightness, attenuation))
+            }
+            LightSection::Rectangle { position, u, v, color, intensity, samples } => {
+                let samples = samples.unwrap_or(DEFAULT_AREA_LIGHT_SAMPLES);
+                Box::new(RectangleLight::new(point(position), vector(u), vector(v), rgb(color), intensity, samples))
+            }
+            LightSection::Disk { position, normal, radius, color, intensity, samples } => {
+                let samples = samples.unwrap_or(DEFAULT_AREA_LIGHT_SAMPLES);
+                Box::new(DiskLight::new(point(position), vector(normal), radius, rgb(color), intensity, samples))
+            }
+            LightSection::Sphere { position, radius, color, intensity, samples } => {
+                let samples = samples.unwrap_or(DEFAULT_AREA_LIGHT_SAMPLES);
+                Box::new(SphereLight::new(point(position), radius, rgb(color), intensity, samples))
+            }
+            LightSection::Spot {
+                position, direction, inner_angle, outer_angle, color, brightness, attenuation, ref ies,
+            } => {
+                if direction_is_zero(direction) {
+                    bail!(self.error_at(light, "the light's direction can't be zero"));
+                }
+                if attenuation_is_invalid(attenuation) {
+                    bail!(self.error_at(light, "attenuation has to be more than 0"));
+                }
+                if !(0. ..=outer_angle).contains(&inner_angle) || outer_angle > 180. {
+                    bail!(self.error_at(light, "expected 0 <= inner_angle <= outer_angle <= 180 degrees"));
+                }
+                let mut spot = SpotLight::new(
+                    point(position),
+                    vector(direction),
+                    Deg(inner_angle),
+                    Deg(outer_angle),
+                    rgb(color),
+                    brightness,
+                    attenuation,
+                );
+                if let Some(ies) = ies {
+                    let profile = IesProfile::load(self.base.join(ies))
+                        .with_context(|| self.error_at(light, "could not load the spot light profile"))?;
+                    spot.set_profile(profile);
+                }
+                Box::new(spot)
+            }
+            LightSection::Environment { intensity, samples } => {
+                let samples = samples.unwrap_or(DEFAULT_AREA_LIGHT_SAMPLES);
+                Box::new(EnvironmentLight::new(environment.clone(), intensity.unwrap_or(1.), samples))
+            }
+        })
+    }
+
+    fn build_texture(&self, name: &str, texture: &TextureSection) -> Result<Arc<dyn Texture>> {
+        let (pattern, space, scale, colors): (Box<dyn Pattern>, _, _, _) = match *texture {
+            TextureSection::Checker { space, scale, colors } => (Box::new(Checker), space, scale, colors),
+            TextureSection::Gradient { direction, space, scale, colors } => {
+                let direction = vector(direction.unwrap_or([0., -1., 0.]));
+                (Box::new(Gradient::new(direction)), space, scale, colors)
+            }
+            TextureSection::Noise { space, scale, colors } => (Box::new(Noise), space, scale, colors),
+            TextureSection::Fbm { octaves, gain, space, scale, colors } => {
+                let fbm = Fbm::new(octaves.unwrap_or(DEFAULT_OCTAVES), gain.unwrap_or(0.5));
+                (Box::new(fbm), space, scale, colors)
+            }
+            TextureSection::Turbulence { octaves, gain, space, scale, colors } => {
+                let turbulence = Turbulence::new(octaves.unwrap_or(DEFAULT_OCTAVES), gain.unwrap_or(0.5));
+                (Box::new(turbulence), space, scale, colors)
+            }
+            TextureSection::Marble { direction, turbulence, octaves, space, scale, colors } => {
+                let marble = Marble::new(
+                    vector(direction.unwrap_or([1., 0., 0.])),
+                    turbulence.unwrap_or(2.),
+                    octaves.unwrap_or(DEFAULT_OCTAVES),
+                );
+                (Box::new(marble), space, scale, colors)
+            }
+            TextureSection::Wood { turbulence, space, scale, colors } => {
+                (Box::new(Wood::new(turbulence.unwrap_or(0.2))), space, scale, colors)
+            }
+            TextureSection::Worley { space, scale, colors } => (Box::new(Worley), space, scale, colors),
+        };
+        let scale = scale.unwrap_or(1.);
+        if scale <= 0. {
+            bail!("{}: texture \"{}\" needs a positive scale", self.path, name);
+        }
+        let space = match space.unwrap_or(TextureSpaceSection::Object) {
+            TextureSpaceSection::Object => TextureSpace::Object,
+            TextureSpaceSection::World => TextureSpace::World,
+        };
+        let colors = colors.unwrap_or([[0, 0, 0], [255, 255, 255]]);
+        Ok(Arc::new(ProceduralTexture::new(pattern, space, scale, [rgb(colors[0]), rgb(colors[1])])))
+    }
+
+    fn build_material(&self, textures: &HashMap<&str, Arc<dyn Texture>>, name: &str, material: &MaterialSection) -> Result<Material> {
+        Ok(match *material {
+            MaterialSection::Lambert {
+                color, albedo, lambert, reflective, phong, alpha, roughness, reflection_samples, ref texture,
+                ref normal_map, normal_strength, ref bump_map, bump_height,
+            } => {
+                let reflection = ReflectionBehavior::new_glossy(
+                    reflective,
+                    roughness.unwrap_or(0.),
+                    reflection_samples.unwrap_or(DEFAULT_GLOSSY_SAMPLES),
+                );
+                let texture = self.texture(textures, name, texture, "texture")?;
+                let mut material = Material::new_glossy_lambert_material(rgb(color), texture, albedo, lambert, reflection, phong, alpha);
+                material.normal_perturbation = self.normal_perturbation(name, normal_map, normal_strength, bump_map, bump_height)?;
+                material
+            }
+            MaterialSection::Principled {
+                color, metallic, roughness, specular, ior, ref texture, ref metallic_texture, ref roughness_texture,
+                ref normal_map, normal_strength, ref bump_map, bump_height,
+            } => {
+                let bsdf = PrincipledBsdf::new(
+                    rgb(color),
+                    metallic.unwrap_or(0.),
+                    roughness.unwrap_or(0.5),
+                    specular.unwrap_or(1.),
+                    ior.unwrap_or(1.5),
+                );
+                let mut principled = PrincipledBehavior::new(bsdf, rgb(color), 1.0);
+                if let Some(texture) = self.texture(textures, name, texture, "texture")? {
+                    principled.set_texture(texture);
+                }
+                if let Some(texture) = self.texture(textures, name, metallic_texture, "metallic texture")? {
+                    principled.set_metallic_texture(texture);
+                }
+                if let Some(texture) = self.texture(textures, name, roughness_texture, "roughness texture")? {
+                    principled.set_roughness_texture(texture);
+                }
+                let mut material = Material::new_principled_material_from(principled);
+                material.normal_perturbation = self.normal_perturbation(name, normal_map, normal_strength, bump_map, bump_height)?;
+                material
+            }
+            MaterialSection::Dielectric { ior, color, absorption_distance } => {
+                let absorption_distance = absorption_distance.unwrap_or(1.);
+                if !(absorption_distance > 0. && absorption_distance.is_finite()) {
+                    bail!("{}: material \"{}\" needs a positive absorption_distance", self.path, name);
+                }
+                Material::new_dielectric_material(
+                    ior.unwrap_or(1.5),
+                    rgb(color.unwrap_or([255, 255, 255])),
+                    absorption_distance,
+                )
+            }
+        })
+    }
+
+    // A texture by name, or else an image file. Colors are sRGB, other images are loaded
+    // as data. Like spot light profiles, these come from tagged tables without spans.
+    fn texture(
+        &self,
+        textures: &HashMap<&str, Arc<dyn Texture>>,
+        material: &str,
+        name: &Option<String>,
+        kind: &str,
+    ) -> Result<Option<Arc<dyn Texture>>> {
+        let name = match name {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        if let Some(texture) = textures.get(name.as_str()) {
+            return Ok(Some(texture.clone()));
+        }
+        let path = self.base.join(name);
+        let texture = if kind == "texture" { ImageTexture::load(path) } else { ImageTexture::load_data(path) }
+            .with_context(|| format!("{}: {} of material \"{}\"", self.path, kind, material))?;
+        Ok(Some(Arc::new(texture)))
+    }
+
+    fn normal_perturbation(
+        &self,
+        material: &str,
+        normal_map: &Option<String>,
+        normal_strength: Option<f64>,
+        bump_map: &Option<String>,
+        bump_height: Option<f64>,
+    ) -> Result<Option<Arc<dyn NormalPerturbation>>> {
+        let load = |path: &str, kind: &str| ImageTexture::load_data(self.base.join(path))
+            .with_context(|| format!("{}: {} of material \"{}\"", self.path, kind, material));
+        Ok(match (normal_map, bump_map) {
+            (Some(_), Some(_)) => {
+                bail!("{}: material \"{}\" has both a normal map and a bump map", self.path, material)
+            }
+            (Some(path), None) => {
+                Some(Arc::new(NormalMap::new(load(path, "normal map")?, normal_strength.unwrap_or(1.))))
+            }
+            (None, Some(path)) => {
+                Some(Arc::new(BumpMap::new(load(path, "bump map")?, bump_height.unwrap_or(0.01))))
+            }
+            (None, None) => None,
+        })
+    }
+
+    fn material(&self, materials: &HashMap<&str, Material>, name: &Spanned<String>) -> Result<Material> {
+        materials.get(name.get_ref().as_str())
+            .cloned()
+            .ok_or_else(|| anyhow!(self.error_at(name, &format!("unknown material \"{}\"", name.get_ref()))))
+    }
+
+    fn resolve(&self, path: &Spanned<String>) -> Result<PathBuf> {
+        let resolved = self.base.join(path.get_ref());
+        if !resolved.exists() {
+            bail!(self.error_at(path, &format!("{} does not exist", resolved.display())));
+        }
+        Ok(resolved)
+    }
+
+    // Formats an error pointing at the line and column of a value in the scene file
+    fn error_at<T>(&self, value: &Spanned<T>, message: &str) -> String {
+        let before = &self.source[..value.span().start];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+        format!("{}:{}:{}: {}", self.path, line, column, message)
+    }
+}
+
+
+fn point(p: [f64; 3]) -> Point3<f64> {
+    Point3 {x: p[0], y: p[1], z: p[2]}
+}
+
+fn vector(v: [f64; 3]) -> Vector3<f64> {
+    vector3(v[0], v[1], v[2])
+}
+
+fn rgb(c: [u8; 3]) -> Vector3<f64> {
+    color_vec(c[0], c[1], c[2])
+}
@@ -1,73 +1,162 @@
-extern crate cgmath;
-extern crate image;
-extern crate pbr;
+extern crate clap;
+extern crate raytracer;
 
-pub mod common;
-pub mod tracer;
-pub mod material;
-pub mod behavior;
-pub mod geometry;
-pub mod lighting;
+use clap::Parser;
+use raytracer::{common, loader, output, save_with_aovs, statusln, Aov, ExrPrecision, Integrator, PathSettings, ToneMap};
+use std::process;
 
-use common::*; 
-use tracer::*;
-use material::*;
-use geometry::{model::{Model}, sphere::Sphere, aabb::AABB, scene::Scene};
+// Exit codes
+const RENDER_FAILED: i32 = 1;
+const BAD_INPUT: i32 = 2;
 
-use cgmath::{Vector3, Point3};
+#[derive(Parser)]
+#[command(name = "raytracer", about = "Renders a TOML scene description to an image")]
+struct Args {
+    /// Scene description file
+    #[arg(default_value = "./scenes/ufo.toml")]
+    scene: String,
+
+    /// Output image, format chosen by extension (.png, .exr, .hdr, ...)
+    #[arg(short, long, default_value = "./bruh.png")]
+    output: String,
+
+    /// Overrides the scene's resolution, e.g. 1920x1080
+    #[arg(short, long, value_parser = parse_resolution)]
+    resolution: Option<(u32, u32)>,
+
+    /// Overrides the scene's samples per pixel
+    #[arg(short, long)]
+    samples: Option<u32>,
+
+    /// Worker threads, 0 uses every available core
+    #[arg(short, long)]
+    threads: Option<usize>,
+
+    /// Overrides the scene's random seed
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Overrides the scene's exposure, in stops
+    #[arg(short, long, allow_negative_numbers = true)]
+    exposure: Option<f64>,
+
+    /// Overrides the scene's tone mapping: clamp, reinhard or aces
+    #[arg(long)]
+    tone_map: Option<ToneMap>,
+
+    /// Overrides the scene's EXR sample type: half or float
+    #[arg(long)]
+    exr_precision: Option<ExrPrecision>,
+
+    /// Overrides the scene's AOVs, comma separated: depth, normal, albedo, position, entity_id, material_id.
+    /// They become layers of .exr outputs, and separate images for other formats.
+    #[arg(long, value_delimiter = ',')]
+    aov: Option<Vec<Aov>>,
+
+    /// Overrides the scene's integrator: whitted, or path for global illumination
+    #[arg(long)]
+    integrator: Option<Integrator>,
+
+    /// Overrides the path length limit, implies --integrator path. Whitted has a fixed depth,
+    /// so this can't be combined with --integrator whitted.
+    #[arg(long)]
+    max_bounces: Option<u32>,
+
+    /// Don't print progress
+    #[arg(short, long)]
+    quiet: bool,
+}
 
 fn main() {
-    println!("MAIN!");
-
-    let raytracer = RayTracer::new_default_renderer((3840, 2160));
-
-    let mut world = RayTracer::new_empty_world("./cubemaps/hd_blue_sunset");
-
-    let mat1 = Material::new_lambert_material(color_vec(100, 100, 200), 0.8, 1.0, 0.01, 0.1, 20);
-    let mat2 = Material::new_lambert_material(color_vec(0, 0, 0), 0.8, 0.0, 1.0, 0.1, 20);
-
-    let sphere = Sphere::new(
-        Point3 {
-            x: -3.0,
-            y: 0.0,
-            z: 5.0,
-        },
-        1.0,
-        mat1,
-    );
-    let sphere2 = Sphere::new(
-        Point3 {
-            x: 2.0,
-            y: 0.0,
-            z: 8.0,
-        },
-        1.0,
-        mat2,
-    );
-
-    let burger = Model::new(
-        "./obj/ufo_fix.obj",
-        Material::new_lambert_material(color_vec(100, 100, 50), 1.0, 1.0, 0.0, 0.3, 20),
-        Point3 {x: 0.0, y: 30.0, z: 70.0},
-        Vector3 {x: 1.0, y: -1.0, z: 1.0}
-    );
-
-    // INSANELY SLOW WITH ONLY 3 OBJECTS IN THE SCENE
-    // ESPECIALLY WITH MULTITHREADING
-    //entity_enum!(SceneType, Sphere, Model);
-    //let scene = Box::new(scene!(SceneType, Point3{x: 0., y:0., z:0.}, Model(burger), Sphere(sphere), Sphere(sphere2)));
-    //world.entities.push(scene);
-    
-    world.entities.push(Box::new(burger));
-    world.entities.push(Box::new(sphere));
-    world.entities.push(Box::new(sphere2));
-
-    raytracer.render("./bruh.png".to_owned(), world);
+    // Usage errors exit with code 2 from clap
+    let args = Args::parse();
+    common::set_quiet(args.quiet);
+    if args.max_bounces.is_some() && args.integrator == Some(Integrator::Whitted) {
+        eprintln!("--max-bounces only applies to the path integrator, not --integrator whitted");
+        process::exit(BAD_INPUT);
+    }
+    if let Err(e) = output::check_format(&args.output) {
+        eprintln!("Could not save {}: {:#}", args.output, e);
+        process::exit(BAD_INPUT);
+    }
+
+    let (mut raytracer, world) = match loader::load_scene(&args.scene) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            process::exit(BAD_INPUT);
+        }
+    };
+
+    if let Some(resolution) = args.resolution {
+        raytracer.set_image_size(resolution);
+    }
+    let settings = raytracer.settings_mut();
+    if let Some(samples) = args.samples {
+        settings.samples_per_pixel = samples;
+    }
+    if let Some(threads) = args.threads {
+        settings.threads = threads;
+    }
+    if let Some(seed) = args.seed {
+        settings.seed = seed;
+    }
+
+    if let Some(exposure) = args.exposure {
+        settings.output.exposure = exposure;
+    }
+    if let Some(tone_map) = args.tone_map {
+        settings.output.tone_map = tone_map;
+    }
+    if let Some(precision) = args.exr_precision {
+        settings.output.exr_precision = precision;
+    }
+    if let Some(aovs) = &args.aov {
+        settings.aovs = Aov::unique(aovs);
+    }
+    if let Some(integrator) = args.integrator {
+        settings.integrator = integrator;
+    }
+    if let Some(max_bounces) = args.max_bounces {
+        // Keep the scene's path settings when it already traces paths
+        let mut path = match settings.integrator {
+            Integrator::PathTracing(path) => path,
+            Integrator::Whitted => PathSettings::default(),
+        };
+        path.max_bounces = max_bounces;
+        settings.integrator = Integrator::PathTracing(path);
+    }
+    if let Some(progressive) = settings.progressive.as_mut() {
+        progressive.snapshot_path.get_or_insert_with(|| args.output.clone());
+    }
+
+    let settings = raytracer.settings();
+    let saved = if settings.aovs.is_empty() {
+        raytracer.render(&world).save(&args.output, &settings.output)
+    } else {
+        let (framebuffer, aovs) = raytracer.render_with_aovs(&world);
+        save_with_aovs(&args.output, &framebuffer, &settings.output, &aovs, &settings.aovs)
+    };
+    if let Err(e) = saved {
+        eprintln!("Could not save {}: {:#}", args.output, e);
+        process::exit(RENDER_FAILED);
+    }
+    statusln!("Saved {}", args.output);
+}
+
+fn parse_resolution(s: &str) -> Result<(u32, u32), String> {
+    let (width, height) = s.split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got \"{}\"", s))?;
+    let width: u32 = width.trim().parse().map_err(|_| format!("invalid width \"{}\"", width))?;
+    let height: u32 = height.trim().parse().map_err(|_| format!("invalid height \"{}\"", height))?;
+    if width == 0 || height == 0 {
+        return Err("resolution must be at least 1x1".to_owned());
+    }
+    Ok((width, height))
 }
 
 // Todo:
-// - do multiple ray averages per pixel as an option (anti-aliasing)
-// - do more advanced materials, shadows, reflections, refractions
+// - do more advanced materials
 // - make this a published rust crate with instructions on how to use it
 // - add more ray collider shapes like cubes, try blending between these like Sebastian Lague
 // - try adding in OBJ file support by creating a triangle ray collider
@@ -1,22 +1,45 @@
 extern crate cgmath;
 
-use std::{sync::Arc};
+use std::sync::Arc;
 
-use crate::common::{RayBehavior, color_vec};
+use crate::bsdf::PrincipledBsdf;
+use crate::common::{ColliderResult, RayBehavior, color_vec};
 use crate::behavior::cubemap::CubemapBehavior;
+use crate::behavior::dielectric::DielectricBehavior;
 use crate::behavior::lambert::LambertBehavior;
 use crate::behavior::phong::PhongBehavior;
+use crate::behavior::principled::PrincipledBehavior;
 use crate::behavior::reflection::ReflectionBehavior;
+use crate::bump::NormalPerturbation;
+use crate::texture::Texture;
 
-use cgmath::{Vector3};
+use anyhow::Result;
+use cgmath::{ElementWise, Vector3};
 
 #[derive(Clone)]
 pub struct Material {
     pub shaders: Vec<Arc<dyn RayBehavior>>,
     pub color: Vector3<f64>,
+    // Multiplies color where set
+    pub texture: Option<Arc<dyn Texture>>,
+    // Normal or bump map
+    pub normal_perturbation: Option<Arc<dyn NormalPerturbation>>,
+    // Lets light through, like glass. What shows through replaces the ambient term.
+    pub transmissive: bool,
+    // For the material id AOV, handed out by the loader in the same order on every run. 0
+    // until then, like the background.
+    pub id: u32,
 }
 
-impl Material { 
+impl Material {
+    // Surface color at a hit, without any lighting
+    pub fn albedo(&self, collision: &ColliderResult) -> Vector3<f64> {
+        match &self.texture {
+            Some(texture) => self.color.mul_element_wise(texture.color(collision)),
+            None => self.color,
+        }
+    }
+
     pub fn new_lambert_material(
         color: Vector3<f64>,
         albedo: f64,
@@ -25,19 +48,88 @@ impl Material {
         phong: f64,
         alpha: i32,
     ) -> Material {
-        let lambert_behavior = LambertBehavior::new(albedo, lambert, color);
-        let ref_be = ReflectionBehavior::new(reflective);
+        let reflection = ReflectionBehavior::new(reflective);
+        Material::new_glossy_lambert_material(color, None, albedo, lambert, reflection, phong, alpha)
+    }
+
+    // Like new_lambert_material with any reflection, such as ReflectionBehavior::new_glossy,
+    // and a texture multiplying the color
+    pub fn new_glossy_lambert_material(
+        color: Vector3<f64>,
+        texture: Option<Arc<dyn Texture>>,
+        albedo: f64,
+        lambert: f64,
+        reflection: ReflectionBehavior,
+        phong: f64,
+        alpha: i32,
+    ) -> Material {
+        let mut lambert_behavior = LambertBehavior::new(albedo, lambert, color);
+        if let Some(texture) = &texture {
+            lambert_behavior.set_texture(texture.clone());
+        }
         let phong_behavior = PhongBehavior::new(phong, alpha);
-        let shaders: Vec<Arc<dyn RayBehavior>> = vec![Arc::new(lambert_behavior), Arc::new(ref_be), Arc::new(phong_behavior)];
-        Material { shaders, color }
+        let shaders: Vec<Arc<dyn RayBehavior>> = vec![Arc::new(lambert_behavior), Arc::new(reflection), Arc::new(phong_behavior)];
+        Material { shaders, color, texture, normal_perturbation: None, transmissive: false, id: 0 }
+    }
+
+    // Energy conserving alternative to new_lambert_material, see PrincipledBsdf. The texture
+    // multiplies the base color.
+    pub fn new_principled_material(
+        base_color: Vector3<f64>,
+        texture: Option<Arc<dyn Texture>>,
+        metallic: f64,
+        roughness: f64,
+        specular: f64,
+        ior: f64,
+    ) -> Material {
+        let bsdf = PrincipledBsdf::new(base_color, metallic, roughness, specular, ior);
+        let mut principled = PrincipledBehavior::new(bsdf, base_color, 1.0);
+        if let Some(texture) = texture {
+            principled.set_texture(texture);
+        }
+        Material::new_principled_material_from(principled)
+    }
+
+    // For principled behaviors with more textures, like metallic and roughness ones
+    pub fn new_principled_material_from(principled: PrincipledBehavior) -> Material {
+        let color = principled.base_color();
+        let texture = principled.texture();
+        let shaders: Vec<Arc<dyn RayBehavior>> = vec![Arc::new(principled)];
+        Material { shaders, color, texture, normal_perturbation: None, transmissive: false, id: 0 }
+    }
+
+    // Glass that tints light to color over every absorption_distance (> 0) travelled inside
+    // it, white is perfectly clear
+    pub fn new_dielectric_material(ior: f64, color: Vector3<f64>, absorption_distance: f64) -> Material {
+        assert!(
+            absorption_distance > 0. && absorption_distance.is_finite(),
+            "absorption_distance must be positive, got {}",
+            absorption_distance,
+        );
+        let absorption = |c: f64| -c.clamp(1e-6, 1.).ln() / absorption_distance;
+        let dielectric = DielectricBehavior::new(
+            ior,
+            Vector3 {x: absorption(color.x), y: absorption(color.y), z: absorption(color.z)},
+            1.0,
+        );
+        let shaders: Vec<Arc<dyn RayBehavior>> = vec![Arc::new(dielectric)];
+        Material { shaders, color, texture: None, normal_perturbation: None, transmissive: true, id: 0 }
+    }
+
+    pub fn new_sky_material(cubemap_folder: &str) -> Result<Material> {
+        let cubemap_behavior = CubemapBehavior::new(cubemap_folder, 1.0)?;
+        Ok(Material::new_sky_material_from(Arc::new(cubemap_behavior)))
     }
 
-    pub fn new_sky_material(cubemap_folder: &str) -> Material {
-        let cubemap_behavior = CubemapBehavior::new(cubemap_folder, 1.0);
-        let shaders: Vec<Arc<dyn RayBehavior>> = vec![Arc::new(cubemap_behavior)];
+    // Sky drawn by any behavior, like a cubemap shared with an environment light
+    pub fn new_sky_material_from(sky: Arc<dyn RayBehavior>) -> Material {
         Material {
-            shaders,
+            shaders: vec![sky],
             color: color_vec(0, 0, 0),
+            texture: None,
+            normal_perturbation: None,
+            transmissive: false,
+            id: 0,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,338 @@
+extern crate cgmath;
+
+use crate::behavior::reflection::ReflectionBehavior;
+use crate::bsdf::luminance;
+use crate::bump::{BumpMap, NormalMap, NormalPerturbation};
+use crate::material::Material;
+use crate::texture::{ImageTexture, Texture};
+
+use anyhow::{anyhow, Context, Result};
+use cgmath::Vector3;
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, sync::Arc};
+
+// Wavefront MTL material libraries, referenced by OBJ models with mtllib and usemtl. The
+// illumination models map onto lambert materials (diffuse, Phong highlight and mirror terms,
+// like MTL itself) and the refracting ones (illum 4, 6, 7 and 9) onto dielectrics. Dissolve
+// (d, Tr) would need partially transparent surfaces, which the tracer doesn't have, so it is
+// ignored with a warning. obj-rs has a parser too, but it panics on some valid statements and
+// rejects texture options, so this reads the files itself.
+//
+// Kd, Ks and Tf are used as linear values, the way exporters like Blender write them, while
+// map_Kd images are sRGB decoded like every color texture. Their product is the diffuse color.
+
+// Height of white in bump maps, in model units, scaled by the -bm option
+const BUMP_HEIGHT: f64 = 0.01;
+
+// Statement values, with the defaults of the MTL specification
+struct MtlMaterial {
+    diffuse: Vector3<f64>,
+    specular: Vector3<f64>,
+    shininess: f64,
+    ior: f64,
+    dissolve: f64,
+    transmission: Vector3<f64>,
+    illumination: Option<u32>,
+    diffuse_map: Option<String>,
+    // With the -bm multiplier
+    bump_map: Option<(String, f64)>,
+    normal_map: Option<String>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        MtlMaterial {
+            diffuse: Vector3 {x: 0.8, y: 0.8, z: 0.8},
+            specular: Vector3 {x: 0., y: 0., z: 0.},
+            shininess: 0.,
+            ior: 1.5,
+            dissolve: 1.,
+            transmission: Vector3 {x: 1., y: 1., z: 1.},
+            illumination: None,
+            diffuse_map: None,
+            bump_map: None,
+            normal_map: None,
+        }
+    }
+}
+
+// Materials for faces without one, or with one missing from the libraries: the MTL default
+// of a plain light gray
+pub fn default_material() -> Material {
+    MtlMaterial::default().build(Path::new("."), &mut HashMap::new())
+        .expect("the default material has no textures")
+}
+
+// Every material of the library at path with its name, in file order. Texture paths are
+// relative to the library.
+pub fn load_mtl(path: &Path) -> Result<Vec<(String, Material)>> {
+    let source = fs::read_to_string(path)
+        .with_context(|| format!("Could not read material library {}", path.display()))?;
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    // Images shared by several materials are only loaded once
+    let mut textures = HashMap::new();
+    parse(&source)
+        .with_context(|| format!("Could not parse material library {}", path.display()))?
+        .into_iter()
+        .map(|(name, material)| {
+            if material.dissolve < 1. && !material.transparent() {
+                eprintln!(
+                    "{}: ignoring the dissolve of material \"{}\", only illum 4, 6, 7 and 9 are transparent",
+                    path.display(), name,
+                );
+            }
+            let built = material.build(base, &mut textures)
+                .with_context(|| format!("{}: material \"{}\"", path.display(), name))?;
+            Ok((name, built))
+        })
+        .collect()
+}
+
+fn parse(source: &str) -> Result<Vec<(String, MtlMaterial)>> {
+    let mut materials: Vec<(String, MtlMaterial)> = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        let statement = match words.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+        let args: Vec<&str> = words.collect();
+        let error = |message: &str| anyhow!("line {}: {}", index + 1, message);
+        if statement == "newmtl" {
+            let name = args.join(" ");
+            if name.is_empty() {
+                return Err(error("newmtl needs a name"));
+            }
+            materials.push((name, MtlMaterial::default()));
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some((_, material)) => material,
+            None => return Err(error(&format!("{} before any newmtl", statement))),
+        };
+        let number = |args: &[&str]| -> Result<f64> {
+            match args {
+                [value] => value.parse().map_err(|_| error(&format!("invalid number \"{}\"", value))),
+                _ => Err(error(&format!("{} takes one number", statement))),
+            }
+        };
+        match statement {
+            "Kd" => material.diffuse = parse_color(&args).ok_or_else(|| error("invalid Kd color"))?,
+            "Ks" => material.specular = parse_color(&args).ok_or_else(|| error("invalid Ks color"))?,
+            "Tf" => material.transmission = parse_color(&args).ok_or_else(|| error("invalid Tf color"))?,
+            "Ns" => material.shininess = number(&args)?,
+            "Ni" => material.ior = number(&args)?,
+            "d" => material.dissolve = number(&args)?,
+            "Tr" => material.dissolve = 1. - number(&args)?,
+            "illum" => material.illumination = Some(number(&args)? as u32),
+            "map_Kd" => material.diffuse_map = Some(parse_map(&args).0),
+            "map_Bump" | "map_bump" | "bump" => material.bump_map = Some(parse_map(&args)),
+            "norm" => material.normal_map = Some(parse_map(&args).0),
+            // Ambient, emission, the other maps and vendor extensions have no equivalent here
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+// "r g b", "r" for gray. The xyz and spectral forms are not supported.
+fn parse_color(args: &[&str]) -> Option<Vector3<f64>> {
+    let values = args.iter().map(|a| a.parse::<f64>().ok()).collect::<Option<Vec<f64>>>()?;
+    match values[..] {
+        [r] => Some(Vector3 {x: r, y: r, z: r}),
+        [r, g, b] => Some(Vector3 {x: r, y: g, z: b}),
+        _ => None,
+    }
+}
+
+// File name of a texture statement, after its options, and the -bm bump multiplier
+fn parse_map(args: &[&str]) -> (String, f64) {
+    let mut bump_multiplier = 1.;
+    let mut i = 0;
+    while i < args.len() && args[i].starts_with('-') {
+        let option = args[i];
+        i += 1;
+        // Options take up to three numbers (-o, -s, -t) or a fixed number of words
+        let count = match option {
+            "-o" | "-s" | "-t" => args[i..].iter().take(3).take_while(|a| a.parse::<f64>().is_ok()).count(),
+            "-mm" => 2,
+            _ => 1,
+        };
+        if option == "-bm" {
+            bump_multiplier = args.get(i).and_then(|a| a.parse().ok()).unwrap_or(1.);
+        }
+        i += count;
+    }
+    (args[i.min(args.len())..].join(" "), bump_multiplier)
+}
+
+impl MtlMaterial {
+    // Illumination models with refraction
+    fn transparent(&self) -> bool {
+        matches!(self.illumination, Some(4) | Some(6) | Some(7) | Some(9))
+    }
+
+    fn build(&self, base: &Path, textures: &mut HashMap<PathBuf, Arc<ImageTexture>>) -> Result<Material> {
+        if self.transparent() {
+            // Clear glass tinted by the transmission filter
+            return Ok(Material::new_dielectric_material(self.ior, self.transmission, 1.));
+        }
+        let texture: Option<Arc<dyn Texture>> = match &self.diffuse_map {
+            Some(file) => {
+                let path = base.join(file);
+                let texture = match textures.get(&path) {
+                    Some(texture) => texture.clone(),
+                    None => {
+                        let texture = Arc::new(ImageTexture::load(&path).context("map_Kd")?);
+                        textures.insert(path, texture.clone());
+                        texture
+                    }
+                };
+                Some(texture)
+            }
+            None => None,
+        };
+        let specular = luminance(self.specular);
+        // 0 and 1 are diffuse only, 2 adds highlights and 3 and up mirror reflections
+        let illumination = self.illumination.unwrap_or(if specular > 0. { 2 } else { 1 });
+        let phong = if illumination >= 2 { specular } else { 0. };
+        let reflective = if illumination >= 3 { specular } else { 0. };
+        let mut material = Material::new_glossy_lambert_material(
+            self.diffuse,
+            texture,
+            1.,
+            1.,
+            ReflectionBehavior::new(reflective),
+            phong,
+            self.shininess.round().max(1.) as i32,
+        );
+        material.normal_perturbation = self.normal_perturbation(base)?;
+        Ok(material)
+    }
+
+    fn normal_perturbation(&self, base: &Path) -> Result<Option<Arc<dyn NormalPerturbation>>> {
+        if let Some(file) = &self.normal_map {
+            let image = ImageTexture::load_data(base.join(file)).context("norm")?;
+            return Ok(Some(Arc::new(NormalMap::new(image, 1.))));
+        }
+        if let Some((file, multiplier)) = &self.bump_map {
+            let image = ImageTexture::load_data(base.join(file)).context("map_Bump")?;
+            return Ok(Some(Arc::new(BumpMap::new(image, BUMP_HEIGHT * multiplier))));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: f64) -> Vector3<f64> {
+        Vector3 {x: value, y: value, z: value}
+    }
+
+    #[test]
+    fn unset_statements_keep_the_defaults() {
+        let materials = parse("newmtl plain\n").unwrap();
+        assert_eq!(materials.len(), 1);
+        let (name, material) = &materials[0];
+        assert_eq!(name, "plain");
+        assert_eq!(material.diffuse, gray(0.8));
+        assert_eq!(material.specular, gray(0.));
+        assert_eq!(material.transmission, gray(1.));
+        assert_eq!((material.shininess, material.ior, material.dissolve), (0., 1.5, 1.));
+        assert_eq!(material.illumination, None);
+        assert!(material.diffuse_map.is_none() && material.bump_map.is_none() && material.normal_map.is_none());
+    }
+
+    #[test]
+    fn parses_statements() {
+        let source = "# exported\n\
+            newmtl Red Paint\n\
+            Kd 0.8 0.1 0.1 # comment\n\
+            \tKs 0.5\n\
+            Ns 96.0\n\
+            Ka 1 1 1\n\
+            illum 3\n\
+            map_Kd paint.png\n\
+            \n\
+            newmtl glass\n\
+            Tf 0.9 1 0.9\n\
+            Ni 1.45\n\
+            Tr 0.75\n\
+            illum 7\n\
+            norm -bm 2 normals.png\n";
+        let materials = parse(source).unwrap();
+        let (name, paint) = &materials[0];
+        assert_eq!(name, "Red Paint");
+        assert_eq!(paint.diffuse, Vector3 {x: 0.8, y: 0.1, z: 0.1});
+        assert_eq!(paint.specular, gray(0.5));
+        assert_eq!(paint.shininess, 96.);
+        assert_eq!(paint.illumination, Some(3));
+        assert_eq!(paint.diffuse_map.as_deref(), Some("paint.png"));
+        assert!(!paint.transparent());
+        let (name, glass) = &materials[1];
+        assert_eq!(name, "glass");
+        assert_eq!(glass.transmission, Vector3 {x: 0.9, y: 1., z: 0.9});
+        assert_eq!(glass.ior, 1.45);
+        assert_eq!(glass.dissolve, 0.25);
+        assert_eq!(glass.normal_map.as_deref(), Some("normals.png"));
+        assert!(glass.transparent());
+    }
+
+    #[test]
+    fn rejects_invalid_statements() {
+        assert!(parse("Kd 1 1 1\n").is_err());
+        assert!(parse("newmtl\n").is_err());
+        assert!(parse("newmtl a\nKd 1 1\n").is_err());
+        assert!(parse("newmtl a\nNs shiny\n").is_err());
+        assert!(parse("newmtl a\nNi 1.5 1.6\n").is_err());
+        let error = parse("newmtl a\n\nd\n").err().unwrap();
+        assert!(error.to_string().starts_with("line 3:"), "{}", error);
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color(&["0.5"]), Some(gray(0.5)));
+        assert_eq!(parse_color(&["1", "0.5", "0"]), Some(Vector3 {x: 1., y: 0.5, z: 0.}));
+        assert_eq!(parse_color(&[]), None);
+        assert_eq!(parse_color(&["1", "0.5"]), None);
+        assert_eq!(parse_color(&["xyz", "1", "1", "1"]), None);
+        assert_eq!(parse_color(&["spectral", "file.rfl"]), None);
+    }
+
+    #[test]
+    fn skips_map_options() {
+        assert_eq!(parse_map(&["wood.png"]), ("wood.png".to_owned(), 1.));
+        assert_eq!(parse_map(&["-bm", "0.5", "bump.png"]), ("bump.png".to_owned(), 0.5));
+        assert_eq!(parse_map(&["-o", "1", "2", "3", "-s", "2", "tiles.png"]), ("tiles.png".to_owned(), 1.));
+        assert_eq!(parse_map(&["-mm", "0", "1", "-clamp", "on", "a.png"]), ("a.png".to_owned(), 1.));
+        assert_eq!(parse_map(&["-blendu", "off", "my", "texture.png"]), ("my texture.png".to_owned(), 1.));
+        assert_eq!(parse_map(&["-bm", "flat", "bump.png"]), ("bump.png".to_owned(), 1.));
+        assert_eq!(parse_map(&["-bm"]), (String::new(), 1.));
+    }
+
+    #[test]
+    fn builds_glass_only_for_refracting_models() {
+        let materials = parse("newmtl a\nd 0.5\nillum 2\nnewmtl b\nillum 4\nnewmtl c\nillum 9\n").unwrap();
+        let built: Vec<Material> = materials.iter()
+            .map(|(_, material)| material.build(Path::new("."), &mut HashMap::new()).unwrap())
+            .collect();
+        assert!(!built[0].transmissive);
+        assert!(built[1].transmissive);
+        assert!(built[2].transmissive);
+    }
+
+    #[test]
+    fn default_material_is_light_gray() {
+        let material = default_material();
+        assert_eq!(material.color, gray(0.8));
+        assert!(material.texture.is_none() && !material.transmissive);
+    }
+
+    #[test]
+    fn missing_textures_are_errors() {
+        let materials = parse("newmtl a\nmap_Kd missing.png\n").unwrap();
+        assert!(materials[0].1.build(Path::new("."), &mut HashMap::new()).is_err());
+    }
+}
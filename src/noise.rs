@@ -0,0 +1,110 @@
+extern crate cgmath;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+// Noise functions for procedural textures. Lattice cells are hashed instead of looked up in
+// a permutation table, so the patterns never repeat.
+
+// Scrambles the coordinates of a lattice cell, seed picks independent values for the same cell
+fn hash(x: i64, y: i64, z: i64, seed: u32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(73856093)
+        ^ (y as u32).wrapping_mul(19349663)
+        ^ (z as u32).wrapping_mul(83492791)
+        ^ seed.wrapping_mul(0x9e3779b9);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^ (h >> 16)
+}
+
+// Dot product of (x, y, z) with one of the twelve cube edge directions (Perlin 2002)
+fn gradient(hash: u32, x: f64, y: f64, z: f64) -> f64 {
+    match hash % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn mix(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+// Improved Perlin noise, smooth with features about one unit across, in roughly [-1, 1]
+pub fn perlin(p: Point3<f64>) -> f64 {
+    let cell = Point3 {x: p.x.floor(), y: p.y.floor(), z: p.z.floor()};
+    let (x, y, z) = (p.x - cell.x, p.y - cell.y, p.z - cell.z);
+    let (i, j, k) = (cell.x as i64, cell.y as i64, cell.z as i64);
+    let corner = |di: i64, dj: i64, dk: i64| {
+        gradient(hash(i + di, j + dj, k + dk, 0), x - di as f64, y - dj as f64, z - dk as f64)
+    };
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+    mix(
+        mix(mix(corner(0, 0, 0), corner(1, 0, 0), u), mix(corner(0, 1, 0), corner(1, 1, 0), u), v),
+        mix(mix(corner(0, 0, 1), corner(1, 0, 1), u), mix(corner(0, 1, 1), corner(1, 1, 1), u), v),
+        w,
+    )
+}
+
+// Octaves of Perlin noise, each lacunarity times finer and gain times weaker than the last,
+// in roughly [-1, 1]
+pub fn fbm(p: Point3<f64>, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+    octave_sum(p, octaves, lacunarity, gain, perlin)
+}
+
+// Like fbm with the absolute value of every octave, giving creases where the noise crosses
+// zero. In [0, 1], mostly below 0.5.
+pub fn turbulence(p: Point3<f64>, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+    octave_sum(p, octaves, lacunarity, gain, |p| perlin(p).abs())
+}
+
+// Sum normalized by the total amplitude
+fn octave_sum<F: Fn(Point3<f64>) -> f64>(p: Point3<f64>, octaves: u32, lacunarity: f64, gain: f64, noise: F) -> f64 {
+    let mut sum = 0.;
+    let mut total = 0.;
+    let mut amplitude = 1.;
+    let mut frequency = 1.;
+    for _ in 0..octaves.max(1) {
+        sum += noise(Point3::from_vec(p.to_vec() * frequency)) * amplitude;
+        total += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+    sum / total
+}
+
+// Worley (cellular) noise: distance to the closest of random feature points, one per unit
+// cell. 0 on the points, rarely above 1.
+pub fn worley(p: Point3<f64>) -> f64 {
+    let (i, j, k) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+    let unit = |h: u32| (h as f64) / (u32::MAX as f64 + 1.);
+    let mut closest = f64::MAX;
+    for di in -1..=1 {
+        for dj in -1..=1 {
+            for dk in -1..=1 {
+                let (ci, cj, ck) = (i + di, j + dj, k + dk);
+                let feature = Vector3 {
+                    x: ci as f64 + unit(hash(ci, cj, ck, 1)),
+                    y: cj as f64 + unit(hash(ci, cj, ck, 2)),
+                    z: ck as f64 + unit(hash(ci, cj, ck, 3)),
+                };
+                closest = closest.min((feature - p.to_vec()).magnitude2());
+            }
+        }
+    }
+    closest.sqrt()
+}
@@ -0,0 +1,98 @@
+extern crate exr;
+
+use anyhow::{bail, Result};
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, SmallVec, WritableImage, f16};
+use image::{codecs::hdr::HdrEncoder, Rgb};
+use std::{fs::File, io::BufWriter, path::Path, str::FromStr};
+
+// Sample type of EXR channels. Half is plenty for color and halves the file size,
+// float keeps full precision for depth and position data.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+impl FromStr for ExrPrecision {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "half" => Ok(ExrPrecision::Half),
+            "float" => Ok(ExrPrecision::Float),
+            _ => Err(format!("unknown EXR precision \"{}\", expected half or float", s)),
+        }
+    }
+}
+
+// One EXR channel, named the way compositors expect: "R", "G", "B" for the main image
+// and "layer.R" style names for everything else
+pub struct ExrChannel {
+    pub name: String,
+    pub samples: Vec<f32>,
+    // Always stored as float, for data like depth and ids that half would mangle
+    pub full_precision: bool,
+}
+
+impl ExrChannel {
+    pub fn new(name: &str, samples: Vec<f32>) -> ExrChannel {
+        ExrChannel { name: name.to_owned(), samples, full_precision: false }
+    }
+
+    pub fn new_full_precision(name: &str, samples: Vec<f32>) -> ExrChannel {
+        ExrChannel { name: name.to_owned(), samples, full_precision: true }
+    }
+}
+
+// Writes a single-part EXR holding every channel, each with width * height samples row by row
+pub fn write_exr<P: AsRef<Path>>(path: P, size: (u32, u32), channels: Vec<ExrChannel>, precision: ExrPrecision) -> Result<()> {
+    let channels: SmallVec<[AnyChannel<FlatSamples>; 4]> = channels.into_iter()
+        .map(|channel| {
+            let samples = match precision {
+                ExrPrecision::Half if !channel.full_precision => {
+                    FlatSamples::F16(channel.samples.into_iter().map(f16::from_f32).collect())
+                }
+                _ => FlatSamples::F32(channel.samples),
+            };
+            AnyChannel::new(channel.name.as_str(), samples)
+        })
+        .collect();
+    let layer = Layer::new(
+        (size.0 as usize, size.1 as usize),
+        LayerAttributes::default(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+    Image::from_layer(layer)
+        .write()
+        .to_file(path)?;
+    Ok(())
+}
+
+// Radiance RGBE, row by row
+pub fn write_hdr<P: AsRef<Path>>(path: P, size: (u32, u32), pixels: &[Rgb<f32>]) -> Result<()> {
+    let file = File::create(path)?;
+    HdrEncoder::new(BufWriter::new(file)).encode(pixels, size.0 as usize, size.1 as usize)?;
+    Ok(())
+}
+
+// Lowercase file extension, empty if there is none
+pub fn extension<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref().extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+// Fails for paths no image can be saved to by extension, so they are caught before rendering
+pub fn check_format<P: AsRef<Path>>(path: P) -> Result<()> {
+    let extension = extension(path);
+    if extension == "exr" || extension == "hdr" {
+        return Ok(());
+    }
+    match image::ImageFormat::from_extension(&extension) {
+        // The AVIF encoder is behind an image feature this crate doesn't enable
+        Some(format) if format.can_write() && format != image::ImageFormat::Avif => Ok(()),
+        _ if extension.is_empty() => bail!("no file extension to pick the image format from"),
+        _ => bail!("can't save .{} images, use .png, .exr, .hdr or another image format", extension),
+    }
+}
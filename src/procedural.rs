@@ -0,0 +1,177 @@
+extern crate cgmath;
+
+use crate::common::{lerp, ColliderResult};
+use crate::noise::{fbm, perlin, turbulence, worley};
+use crate::texture::Texture;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use std::f64::consts::PI;
+
+// Textures computed from the 3D position of hits, so they need no texture coordinates. A
+// Pattern gives a value in [0, 1] that ProceduralTexture blends two colors with.
+
+pub trait Pattern: Sync + Send {
+    fn value(&self, p: Point3<f64>) -> f64;
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum TextureSpace {
+    // Follows the entity it is on, relative to its position
+    Object,
+    World,
+}
+
+pub struct ProceduralTexture {
+    pattern: Box<dyn Pattern>,
+    space: TextureSpace,
+    scale: f64,
+    colors: [Vector3<f64>; 2],
+}
+
+impl ProceduralTexture {
+    // scale is the size of the pattern in world units, the length of a checker square or
+    // of a noise feature. colors are the colors at values 0 and 1.
+    pub fn new(pattern: Box<dyn Pattern>, space: TextureSpace, scale: f64, colors: [Vector3<f64>; 2]) -> ProceduralTexture {
+        ProceduralTexture { pattern, space, scale, colors }
+    }
+
+    fn point(&self, collision: &ColliderResult) -> Point3<f64> {
+        let p = match self.space {
+            TextureSpace::Object => collision.object_position,
+            TextureSpace::World => collision.position,
+        };
+        Point3::from_vec(p.to_vec() / self.scale)
+    }
+}
+
+impl Texture for ProceduralTexture {
+    fn color(&self, collision: &ColliderResult) -> Vector3<f64> {
+        lerp(self.colors[0], self.colors[1], self.value(collision))
+    }
+
+    // The pattern itself, whatever the colors
+    fn value(&self, collision: &ColliderResult) -> f64 {
+        self.pattern.value(self.point(collision)).clamp(0., 1.)
+    }
+}
+
+// Alternating unit cubes
+pub struct Checker;
+
+impl Pattern for Checker {
+    fn value(&self, p: Point3<f64>) -> f64 {
+        let sum = p.x.floor() + p.y.floor() + p.z.floor();
+        sum.rem_euclid(2.)
+    }
+}
+
+// From 0 at the origin to 1 one unit along direction
+pub struct Gradient {
+    direction: Vector3<f64>,
+}
+
+impl Gradient {
+    pub fn new(direction: Vector3<f64>) -> Gradient {
+        Gradient { direction: direction.normalize() }
+    }
+}
+
+impl Pattern for Gradient {
+    fn value(&self, p: Point3<f64>) -> f64 {
+        p.to_vec().dot(self.direction)
+    }
+}
+
+// Plain Perlin noise
+pub struct Noise;
+
+impl Pattern for Noise {
+    fn value(&self, p: Point3<f64>) -> f64 {
+        0.5 + 0.5 * perlin(p)
+    }
+}
+
+// Octaves of noise, each twice as fine and gain times as strong as the last
+pub struct Fbm {
+    octaves: u32,
+    gain: f64,
+}
+
+impl Fbm {
+    pub fn new(octaves: u32, gain: f64) -> Fbm {
+        Fbm { octaves, gain }
+    }
+}
+
+impl Pattern for Fbm {
+    fn value(&self, p: Point3<f64>) -> f64 {
+        0.5 + 0.5 * fbm(p, self.octaves, 2., self.gain)
+    }
+}
+
+// Octaves of the absolute value of noise, dark creases on a billowy background
+pub struct Turbulence {
+    octaves: u32,
+    gain: f64,
+}
+
+impl Turbulence {
+    pub fn new(octaves: u32, gain: f64) -> Turbulence {
+        Turbulence { octaves, gain }
+    }
+}
+
+impl Pattern for Turbulence {
+    fn value(&self, p: Point3<f64>) -> f64 {
+        2. * turbulence(p, self.octaves, 2., self.gain)
+    }
+}
+
+// Veins across direction, one unit apart and bent by turbulence
+pub struct Marble {
+    direction: Vector3<f64>,
+    turbulence: f64,
+    octaves: u32,
+}
+
+impl Marble {
+    pub fn new(direction: Vector3<f64>, turbulence: f64, octaves: u32) -> Marble {
+        Marble { direction: direction.normalize(), turbulence, octaves }
+    }
+}
+
+impl Pattern for Marble {
+    fn value(&self, p: Point3<f64>) -> f64 {
+        let phase = p.to_vec().dot(self.direction) + self.turbulence * turbulence(p, self.octaves, 2., 0.5);
+        0.5 + 0.5 * (2. * PI * phase).sin()
+    }
+}
+
+// Growth rings around the Y axis, one unit apart and warped by turbulence. The dark late
+// wood at the end of each ring is 1.
+pub struct Wood {
+    turbulence: f64,
+}
+
+impl Wood {
+    pub fn new(turbulence: f64) -> Wood {
+        Wood { turbulence }
+    }
+}
+
+impl Pattern for Wood {
+    fn value(&self, p: Point3<f64>) -> f64 {
+        let radius = (p.x * p.x + p.z * p.z).sqrt();
+        let ring = (radius + self.turbulence * fbm(p, 3, 2., 0.5)).rem_euclid(1.);
+        ring.powi(3)
+    }
+}
+
+// Cells around random points, 0 at the points and growing towards the cell borders
+pub struct Worley;
+
+impl Pattern for Worley {
+    fn value(&self, p: Point3<f64>) -> f64 {
+        worley(p)
+    }
+}
@@ -0,0 +1,269 @@
+extern crate cgmath;
+extern crate rand;
+
+use cgmath::Vector3;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::cell::RefCell;
+
+// Every render thread owns a generator that is reseeded per pixel sample, so any sample
+// can be reproduced regardless of which thread (or pass) ends up computing it.
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(0));
+}
+
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+pub fn seed_pixel(base: u64, x: u32, y: u32, sample: u32) {
+    let key = ((x as u64) << 40) ^ ((y as u64) << 16) ^ sample as u64;
+    seed(splitmix(key ^ splitmix(base)));
+}
+
+// Uniform in [0, 1)
+pub fn random() -> f64 {
+    RNG.with(|rng| rng.borrow_mut().gen::<f64>())
+}
+
+// Jittered point in the unit square for sample i of count, with the samples spread over a
+// grid of strata. Uses two random numbers.
+pub fn stratified(i: u32, count: u32) -> (f64, f64) {
+    let (strata_x, strata_y) = strata(count);
+    let stratum = i % (strata_x * strata_y);
+    let x = ((stratum % strata_x) as f64 + random()) / strata_x as f64;
+    let y = ((stratum / strata_x) as f64 + random()) / strata_y as f64;
+    (x, y)
+}
+
+// Columns and rows of a grid with exactly count cells, as close to square as count allows,
+// so that every cell gets a sample
+fn strata(count: u32) -> (u32, u32) {
+    let count = count.max(1);
+    let mut rows = (count as f64).sqrt() as u32;
+    while !count.is_multiple_of(rows) {
+        rows -= 1;
+    }
+    (count / rows, rows)
+}
+
+// Maps the unit square onto the unit disk while preserving stratification (Shirley-Chiu)
+pub fn concentric_disk(u: f64, v: f64) -> (f64, f64) {
+    let a = 2. * u - 1.;
+    let b = 2. * v - 1.;
+    if a == 0. && b == 0. {
+        return (0., 0.);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, std::f64::consts::FRAC_PI_4 * (b / a))
+    } else {
+        (b, std::f64::consts::FRAC_PI_2 - std::f64::consts::FRAC_PI_4 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+// Direction around the z axis with a density of cos θ / π, returned with the cosine
+pub fn cosine_hemisphere(u: f64, v: f64) -> (Vector3<f64>, f64) {
+    let (x, y) = concentric_disk(u, v);
+    let z = (1. - x * x - y * y).max(0.).sqrt();
+    (Vector3 {x, y, z}, z)
+}
+
+// Piecewise constant density over [0, 1) with one step per value, for sampling in
+// proportion to tabulated values like the luminance of an image row
+pub struct Distribution1D {
+    values: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    pub fn new(values: Vec<f64>) -> Distribution1D {
+        let n = values.len() as f64;
+        let mut cdf = Vec::with_capacity(values.len() + 1);
+        cdf.push(0.);
+        for (i, value) in values.iter().enumerate() {
+            cdf.push(cdf[i] + value.max(0.) / n);
+        }
+        let integral = *cdf.last().unwrap();
+        if integral > 0. {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        } else {
+            // Nothing to go by, fall back to uniform
+            cdf.iter_mut().enumerate().for_each(|(i, c)| *c = i as f64 / n);
+        }
+        Distribution1D { values, cdf, integral }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // Average of the values
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    // Maps uniform u to a point in [0, 1), returns it with its density and step index
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let index = (self.cdf.partition_point(|&c| c <= u) - 1).min(self.len() - 1);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0. { (u - self.cdf[index]) / width } else { 0. };
+        let x = ((index as f64 + offset) / self.len() as f64).min(1. - f64::EPSILON);
+        (x, self.pdf(index), index)
+    }
+
+    pub fn pdf(&self, index: usize) -> f64 {
+        if self.integral > 0. {
+            self.values[index].max(0.) / self.integral
+        } else {
+            1.
+        }
+    }
+}
+
+// Piecewise constant density over the unit square, picking a row by its total and then a
+// column within the row
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    // values holds width * height values row by row
+    pub fn new(values: &[f64], width: usize, height: usize) -> Distribution2D {
+        let rows: Vec<Distribution1D> = values.chunks(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    // Maps uniform (u, v) to a point (x, y) in the unit square, returned with its density
+    pub fn sample(&self, u: f64, v: f64) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample(v);
+        let (x, pdf_x, _) = self.rows[row].sample(u);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        let row = ((y * self.marginal.len() as f64) as usize).min(self.marginal.len() - 1);
+        let column = ((x * self.rows[row].len() as f64) as usize).min(self.rows[row].len() - 1);
+        self.marginal.pdf(row) * self.rows[row].pdf(column)
+    }
+}
+
+fn splitmix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn strata_have_exactly_count_cells() {
+        assert_eq!(strata(0), (1, 1));
+        assert_eq!(strata(1), (1, 1));
+        assert_eq!(strata(3), (3, 1));
+        assert_eq!(strata(6), (3, 2));
+        assert_eq!(strata(16), (4, 4));
+        for count in 1..=256 {
+            let (x, y) = strata(count);
+            assert_eq!(x * y, count);
+            assert!(x >= y);
+        }
+    }
+
+    #[test]
+    fn stratified_samples_cover_every_stratum() {
+        seed(7);
+        for count in 1..=64 {
+            let (strata_x, strata_y) = strata(count);
+            let cells: HashSet<(u32, u32)> = (0..count)
+                .map(|i| {
+                    let (x, y) = stratified(i, count);
+                    assert!((0. ..1.).contains(&x) && (0. ..1.).contains(&y));
+                    ((x * strata_x as f64) as u32, (y * strata_y as f64) as u32)
+                })
+                .collect();
+            assert_eq!(cells.len(), count as usize, "{} samples", count);
+        }
+    }
+
+    // Uniform values spread over [0, 1) without randomness
+    fn uniforms(count: usize) -> impl Iterator<Item = f64> {
+        (0..count).map(move |i| (i as f64 + 0.5) / count as f64)
+    }
+
+    #[test]
+    fn distribution_1d_pdf_integrates_to_one() {
+        let distribution = Distribution1D::new(vec![1., 3., 0., 4.]);
+        assert_eq!(distribution.integral(), 2.);
+        let total: f64 = (0..4).map(|i| distribution.pdf(i) / 4.).sum();
+        assert!((total - 1.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn distribution_1d_samples_match_the_pdf() {
+        let values = vec![1., 3., 0., 4.];
+        let distribution = Distribution1D::new(values.clone());
+        let count = 80000;
+        let mut hits = [0; 4];
+        for u in uniforms(count) {
+            let (x, pdf, index) = distribution.sample(u);
+            assert!((0. ..1.).contains(&x));
+            assert_eq!(index, (x * 4.) as usize);
+            assert_eq!(pdf, distribution.pdf(index));
+            hits[index] += 1;
+        }
+        assert_eq!(hits[2], 0);
+        for (i, &value) in values.iter().enumerate() {
+            let expected = value / 8.;
+            assert!((hits[i] as f64 / count as f64 - expected).abs() < 1e-3, "step {}", i);
+        }
+    }
+
+    #[test]
+    fn distribution_1d_falls_back_to_uniform() {
+        let distribution = Distribution1D::new(vec![0., 0., 0., 0.]);
+        for u in uniforms(100) {
+            let (x, pdf, _) = distribution.sample(u);
+            assert!((x - u).abs() < 1e-12);
+            assert_eq!(pdf, 1.);
+        }
+    }
+
+    #[test]
+    fn distribution_2d_samples_match_the_pdf() {
+        let values = [1., 2., 0., 3., 0., 0., 4., 1., 5.];
+        let distribution = Distribution2D::new(&values, 3, 3);
+        let total: f64 = values.iter().sum();
+        let mut hits = [0; 9];
+        let count = 300;
+        for v in uniforms(count) {
+            for u in uniforms(count) {
+                let ((x, y), pdf) = distribution.sample(u, v);
+                assert!((0. ..1.).contains(&x) && (0. ..1.).contains(&y));
+                assert!((pdf - distribution.pdf(x, y)).abs() < 1e-12);
+                hits[(y * 3.) as usize * 3 + (x * 3.) as usize] += 1;
+            }
+        }
+        for (i, &value) in values.iter().enumerate() {
+            // The density over a cell of area 1/9 is 9 times its share of the total
+            let x = (i % 3) as f64 / 3. + 1. / 6.;
+            let y = (i / 3) as f64 / 3. + 1. / 6.;
+            assert!((distribution.pdf(x, y) - 9. * value / total).abs() < 1e-12);
+            let share = hits[i] as f64 / (count * count) as f64;
+            assert!((share - value / total).abs() < 1e-2, "cell {}", i);
+        }
+    }
+}
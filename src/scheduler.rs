@@ -0,0 +1,110 @@
+use std::sync::{atomic::{AtomicUsize, Ordering}, mpsc};
+use std::thread;
+
+pub const DEFAULT_TILE_SIZE: u32 = 32;
+
+// A rectangle of the image. Tiles are numbered row by row, and the index doubles as the
+// key of the tile's own pixel buffer in the film, so workers never share memory.
+#[derive(Copy, Clone)]
+pub struct Tile {
+    pub index: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    pub fn pixel_count(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+
+    // Image space coordinates of every pixel, row by row
+    pub fn coordinates(&self) -> impl Iterator<Item = (u32, u32)> {
+        let (x0, y0, width) = (self.x, self.y, self.width);
+        (0..self.width * self.height).map(move |i| (x0 + i % width, y0 + i / width))
+    }
+}
+
+// Hands out tiles from a shared counter: whichever worker is free takes the next one,
+// so cheap tiles (open sky) and expensive ones (dense meshes) even out across threads.
+pub struct TileScheduler {
+    image_size: (u32, u32),
+    tile_size: u32,
+    tiles_x: u32,
+    tile_count: usize,
+    next: AtomicUsize,
+}
+
+impl TileScheduler {
+    pub fn new(image_size: (u32, u32), tile_size: u32) -> TileScheduler {
+        let tile_size = tile_size.max(1);
+        let tiles_x = image_size.0.div_ceil(tile_size);
+        let tiles_y = image_size.1.div_ceil(tile_size);
+        TileScheduler {
+            image_size,
+            tile_size,
+            tiles_x,
+            tile_count: (tiles_x * tiles_y) as usize,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.tile_count
+    }
+
+    pub fn tile(&self, index: usize) -> Tile {
+        let x = (index as u32 % self.tiles_x) * self.tile_size;
+        let y = (index as u32 / self.tiles_x) * self.tile_size;
+        Tile {
+            index,
+            x,
+            y,
+            width: self.tile_size.min(self.image_size.0 - x),
+            height: self.tile_size.min(self.image_size.1 - y),
+        }
+    }
+
+    pub fn next_tile(&self) -> Option<Tile> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        if index >= self.tile_count {
+            return None;
+        }
+        Some(self.tile(index))
+    }
+
+    // Renders every tile on a pool of worker threads. Finished tiles are passed to on_tile
+    // on the calling thread as they come in. Each call is a full pass over the image.
+    pub fn run<F, G>(&self, threads: usize, render_tile: F, mut on_tile: G)
+    where
+        F: Fn(Tile) + Sync,
+        G: FnMut(Tile),
+    {
+        let threads = if threads == 0 { available_threads() } else { threads };
+        self.next.store(0, Ordering::Relaxed);
+        let render_tile = &render_tile;
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for _ in 0..threads.min(self.tile_count.max(1)) {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    while let Some(tile) = self.next_tile() {
+                        render_tile(tile);
+                        if sender.send(tile).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+            for tile in receiver {
+                on_tile(tile);
+            }
+        });
+    }
+}
+
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
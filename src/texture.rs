@@ -0,0 +1,113 @@
+extern crate cgmath;
+
+use crate::bsdf::luminance;
+use crate::common::{rgb_vec, ColliderResult};
+use crate::output;
+
+use anyhow::{Context, Result};
+use cgmath::{Vector2, Vector3};
+use image::codecs::hdr::HdrDecoder;
+use std::{fs::File, io::BufReader, path::Path};
+
+// A color that varies over surfaces, looked up at hits
+pub trait Texture: Sync + Send {
+    fn color(&self, collision: &ColliderResult) -> Vector3<f64>;
+
+    // For scalar parameters like roughness, the luminance of the color unless the texture
+    // has values of its own
+    fn value(&self, collision: &ColliderResult) -> f64 {
+        luminance(self.color(collision))
+    }
+}
+
+// Image wrapped over the texture coordinates of hits, with (0, 0) at the bottom left corner
+// like in OBJ files
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    // Linear, row by row from the top
+    pixels: Vec<Vector3<f32>>,
+}
+
+impl ImageTexture {
+    // Radiance .hdr files are used as is, other formats are taken to be sRGB
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ImageTexture> {
+        ImageTexture::decode(path.as_ref(), true)
+    }
+
+    // For data rather than colors, like normal and bump maps: 8-bit values are only scaled
+    // to [0, 1]
+    pub fn load_data<P: AsRef<Path>>(path: P) -> Result<ImageTexture> {
+        ImageTexture::decode(path.as_ref(), false)
+    }
+
+    fn decode(path: &Path, srgb: bool) -> Result<ImageTexture> {
+        let (width, height, pixels) = if output::extension(path) == "hdr" {
+            let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+            let decoder = HdrDecoder::new(BufReader::new(file))
+                .with_context(|| format!("Could not read {}", path.display()))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder.read_image_hdr()
+                .with_context(|| format!("Could not read {}", path.display()))?
+                .into_iter()
+                .map(|p| Vector3 {x: p[0], y: p[1], z: p[2]})
+                .collect();
+            (metadata.width, metadata.height, pixels)
+        } else {
+            let encoded = image::open(path)
+                .with_context(|| format!("Could not read {}", path.display()))?
+                .to_rgb8();
+            let pixels = encoded.pixels()
+                .map(|p| if srgb {
+                    rgb_vec(*p).cast().unwrap()
+                } else {
+                    Vector3 {x: p[0] as f32, y: p[1] as f32, z: p[2] as f32} / 255.
+                })
+                .collect();
+            (encoded.width(), encoded.height(), pixels)
+        };
+        Ok(ImageTexture { width, height, pixels })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Pixel with wrapping coordinates
+    pub fn texel(&self, x: i64, y: i64) -> Vector3<f64> {
+        let x = x.rem_euclid(self.width as i64) as u32;
+        let y = y.rem_euclid(self.height as i64) as u32;
+        self.pixels[(y * self.width + x) as usize].cast().unwrap()
+    }
+
+    // Closest pixel to (s, t) in [0, 1), from the top left corner
+    pub fn nearest(&self, s: f64, t: f64) -> Vector3<f64> {
+        self.texel((s * self.width as f64).floor() as i64, (t * self.height as f64).floor() as i64)
+    }
+
+    // Blend of the four pixels around (s, t), from the top left corner and wrapping around
+    pub fn bilinear(&self, s: f64, t: f64) -> Vector3<f64> {
+        let x = s * self.width as f64 - 0.5;
+        let y = t * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = self.texel(x0, y0) * (1. - fx) + self.texel(x0 + 1, y0) * fx;
+        let bottom = self.texel(x0, y0 + 1) * (1. - fx) + self.texel(x0 + 1, y0 + 1) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+
+    pub fn sample(&self, uv: Vector2<f64>) -> Vector3<f64> {
+        self.bilinear(uv.x, 1. - uv.y)
+    }
+}
+
+impl Texture for ImageTexture {
+    fn color(&self, collision: &ColliderResult) -> Vector3<f64> {
+        self.sample(collision.uv)
+    }
+}
@@ -0,0 +1,158 @@
+extern crate cgmath;
+
+use crate::common::vec_rgb;
+use crate::output::ExrPrecision;
+
+use cgmath::Vector3;
+use std::str::FromStr;
+
+// Operators mapping linear scene radiance to display range [0, 1]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMap {
+    // Everything above 1.0 clips to white
+    Clamp,
+    // x / (1 + x), never clips but flattens highlights
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(format!("unknown tone map \"{}\", expected clamp, reinhard or aces", s)),
+        }
+    }
+}
+
+impl ToneMap {
+    pub fn map(&self, x: f64) -> f64 {
+        let x = x.max(0.);
+        match self {
+            ToneMap::Clamp => x.min(1.),
+            ToneMap::Reinhard => x / (1. + x),
+            ToneMap::Aces => ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).min(1.),
+        }
+    }
+}
+
+// Turns the linear framebuffer into display values: exposure, then tone mapping, then
+// sRGB encoding when quantizing. Floating point formats (EXR, HDR) skip all of it and
+// store the radiance as rendered.
+#[derive(Copy, Clone, Debug)]
+pub struct OutputTransform {
+    // In stops, every +1 doubles the brightness
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    pub exr_precision: ExrPrecision,
+}
+
+impl OutputTransform {
+    pub fn new(exposure: f64, tone_map: ToneMap) -> OutputTransform {
+        OutputTransform { exposure, tone_map, exr_precision: ExrPrecision::Half }
+    }
+
+    // Linear display value, before sRGB encoding
+    pub fn apply(&self, color: Vector3<f64>) -> Vector3<f64> {
+        let scale = 2f64.powf(self.exposure);
+        Vector3 {
+            x: self.tone_map.map(color.x * scale),
+            y: self.tone_map.map(color.y * scale),
+            z: self.tone_map.map(color.z * scale),
+        }
+    }
+
+    pub fn to_rgb8(&self, color: Vector3<f64>) -> image::Rgb<u8> {
+        vec_rgb(self.apply(color))
+    }
+}
+
+impl Default for OutputTransform {
+    fn default() -> Self {
+        OutputTransform::new(0., ToneMap::Clamp)
+    }
+}
+
+pub fn srgb_encode(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1. / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_decode(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::rgb_vec;
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn srgb_round_trips() {
+        for i in 0..=1000 {
+            let x = i as f64 / 1000.;
+            assert!((srgb_decode(srgb_encode(x)) - x).abs() < 1e-12, "{}", x);
+            assert!((srgb_encode(srgb_decode(x)) - x).abs() < 1e-12, "{}", x);
+        }
+    }
+
+    #[test]
+    fn srgb_is_continuous_at_the_linear_segment() {
+        let (below, above) = (srgb_encode(0.0031308 - 1e-9), srgb_encode(0.0031308 + 1e-9));
+        assert!((below - above).abs() < 1e-6);
+        let (below, above) = (srgb_decode(0.04045 - 1e-9), srgb_decode(0.04045 + 1e-9));
+        assert!((below - above).abs() < 1e-6);
+    }
+
+    #[test]
+    fn every_8_bit_value_round_trips() {
+        let transform = OutputTransform::default();
+        for i in 0..=255 {
+            let pixel = image::Rgb([i, i, i]);
+            assert_eq!(transform.to_rgb8(rgb_vec(pixel)), pixel);
+        }
+    }
+
+    #[test]
+    fn tone_maps_stay_in_display_range() {
+        for tone_map in &[ToneMap::Clamp, ToneMap::Reinhard, ToneMap::Aces] {
+            assert_eq!(tone_map.map(0.), 0.);
+            assert_eq!(tone_map.map(-1.), 0.);
+            let mut last = 0.;
+            for i in 1..=1000 {
+                let mapped = tone_map.map(i as f64 / 10.);
+                assert!(mapped >= last && mapped <= 1., "{:?} {}", tone_map, mapped);
+                last = mapped;
+            }
+        }
+        assert_eq!(ToneMap::Clamp.map(0.5), 0.5);
+        assert_eq!(ToneMap::Reinhard.map(1.), 0.5);
+    }
+
+    #[test]
+    fn exposure_doubles_per_stop() {
+        let color = Vector3 {x: 0.1, y: 0.2, z: 0.3};
+        let brighter = OutputTransform::new(1., ToneMap::Clamp).apply(color);
+        assert!((brighter - color * 2.).magnitude() < 1e-12);
+    }
+
+    #[test]
+    fn tone_maps_parse_by_name() {
+        assert_eq!("aces".parse(), Ok(ToneMap::Aces));
+        assert_eq!("reinhard".parse(), Ok(ToneMap::Reinhard));
+        assert!("filmic".parse::<ToneMap>().is_err());
+    }
+}
@@ -3,10 +3,21 @@ extern crate cgmath;
 use crate::common::*;
 use crate::material::*;
 use crate::lighting::*;
-
-use cgmath::{Vector3, Point3, InnerSpace};
-use image::{ImageBuffer, Rgb};
-use std::{sync::Arc, thread, time};
+use crate::camera::Camera;
+use crate::sampling;
+use crate::filter::{BoxFilter, PixelFilter};
+use crate::scheduler::{TileScheduler, DEFAULT_TILE_SIZE};
+use crate::film::{Film, PixelAccumulator};
+use crate::aov::{Aov, AovAccumulator, AovBuffers, AovSample};
+use crate::framebuffer::Framebuffer;
+use crate::tonemap::OutputTransform;
+use crate::integrator::{self, Integrator};
+use crate::statusln;
+
+use anyhow::Result;
+use cgmath::{Deg, EuclideanSpace, Point3, Vector3};
+use pbr::ProgressBar;
+use std::{ops::Range, time};
 
 pub struct RayTracer {
     settings: RenderSettings,
@@ -14,48 +25,110 @@ pub struct RayTracer {
 }
 
 pub struct RenderSettings {
-    image_size: (u32, u32),
+    pub image_size: (u32, u32),
+    pub samples_per_pixel: u32,
+    pub filter: Box<dyn PixelFilter>,
+    // Worker threads, 0 uses every available core
+    pub threads: usize,
+    pub tile_size: u32,
+    // Accumulate passes of samples and write snapshots while rendering
+    pub progressive: Option<ProgressiveSettings>,
+    // Renders with the same seed and settings are identical
+    pub seed: u64,
+    // Used for snapshots, and by callers when saving to display formats
+    pub output: OutputTransform,
+    // AOVs to save next to the beauty image, none are recorded when empty
+    pub aovs: Vec<Aov>,
+    pub integrator: Integrator,
 }
 
-pub struct Camera {
-    size: (f64, f64),
-    lens_factor: (f64, f64),
-    position: Point3<f64>,
+pub struct ProgressiveSettings {
+    pub samples_per_pass: u32,
+    // Stops after the first pass that ends past the budget, even short of samples_per_pixel
+    pub time_budget: Option<time::Duration>,
+    pub snapshot_interval: time::Duration,
+    // No snapshots are written without a path
+    pub snapshot_path: Option<String>,
 }
 
-impl RayTracer {
-    pub const fn default() -> Self {
-        RayTracer {
-            settings: RenderSettings {image_size: (0, 0)},
-            camera: Camera {
-                size: (0., 0.),
-                lens_factor: (0., 0.),
-                position: Point3 {x: 0., y: 0., z: 0.}
-            }
+impl ProgressiveSettings {
+    pub fn new(samples_per_pass: u32, snapshot_interval: time::Duration) -> ProgressiveSettings {
+        ProgressiveSettings {
+            samples_per_pass,
+            time_budget: None,
+            snapshot_interval,
+            snapshot_path: None,
         }
     }
+}
+
+impl RenderSettings {
+    pub fn new(image_size: (u32, u32)) -> RenderSettings {
+        RenderSettings {
+            image_size,
+            samples_per_pixel: 1,
+            filter: Box::new(BoxFilter::default()),
+            threads: 0,
+            tile_size: DEFAULT_TILE_SIZE,
+            progressive: None,
+            seed: 0,
+            output: OutputTransform::default(),
+            aovs: Vec::new(),
+            integrator: Integrator::Whitted,
+        }
+    }
+}
+
+impl RayTracer {
+    pub fn new(settings: RenderSettings, camera: Camera) -> RayTracer {
+        RayTracer { settings, camera }
+    }
 
+    // Looks down +Z from the origin with -Y as up, which is the orientation the scenes were built for
     pub fn new_default_renderer(size: (u32, u32)) -> RayTracer {
+        let camera = Camera::new(
+            Point3 {x: 0., y: 0., z: 0.},
+            Point3 {x: 0., y: 0., z: 1.},
+            Vector3 {x: 0., y: -1., z: 0.},
+            Deg(62.),
+            size.0 as f64 / size.1 as f64,
+        );
         RayTracer {
-            settings: RenderSettings { image_size: size },
-            camera: Camera {
-                size: (160.0, 90.0),
-                lens_factor: (1., 1.),
-                position: Point3 {
-                    x: 0.,
-                    y: 0.,
-                    z: 0.,
-                },
-            },
+            settings: RenderSettings::new(size),
+            camera,
         }
     }
 
-    pub fn new_empty_world(skybox: &str) -> World {
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut RenderSettings {
+        &mut self.settings
+    }
+
+    // Changes the output resolution and keeps the camera's aspect ratio in sync
+    pub fn set_image_size(&mut self, size: (u32, u32)) {
+        self.settings.image_size = size;
+        self.camera.set_aspect(size.0 as f64 / size.1 as f64);
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    // Lit like scenes/ufo.toml: the sun shines along +Y, which is down for the default
+    // renderer's camera
+    pub fn new_empty_world(skybox: &str) -> Result<World> {
         let entities: Vec<Box<dyn Entity>> = Vec::new();
         let sun = DirectionalLight::new(
             Vector3 {
                 x: 1.0,
-                y: -1.0,
+                y: 1.0,
                 z: 1.0,
             },
             color_vec(230, 230, 230),
@@ -64,149 +137,269 @@ impl RayTracer {
 
         let light_sources: Vec<Box<dyn LightSource>> = vec![Box::new(sun)];
 
-        let sky = Material::new_sky_material(skybox);
+        let sky = Material::new_sky_material(skybox)?;
 
-        World {
+        Ok(World {
             entities,
             light_sources,
             sky,
             ambient: 0.15,
-        }
+        })
     }
 
-    pub fn render(self, output: String, world: World) {
-        println!("Rendering...");
+    // Renders into a linear HDR framebuffer, the world is left untouched and can be rendered again
+    pub fn render(&self, world: &World) -> Framebuffer {
+        statusln!("Rendering...");
         let timer = time::Instant::now();
+        let snapshot_path = self.settings.progressive.as_ref().and_then(|p| p.snapshot_path.as_deref());
+        let framebuffer = self.render_film(world, snapshot_path, false).to_framebuffer();
+        statusln!("Finished in {}ms", timer.elapsed().as_millis());
+        framebuffer
+    }
 
-        let mut img: ImageBuffer<image::Rgb<u8>, Vec<_>> = 
-            image::ImageBuffer::new(self.settings.image_size.0, self.settings.image_size.1);
+    // Like render, also recording the AOVs of the primary hits
+    pub fn render_with_aovs(&self, world: &World) -> (Framebuffer, AovBuffers) {
+        statusln!("Rendering...");
+        let timer = time::Instant::now();
+        let snapshot_path = self.settings.progressive.as_ref().and_then(|p| p.snapshot_path.as_deref());
+        let film = self.render_film(world, snapshot_path, true);
+        statusln!("Finished in {}ms", timer.elapsed().as_millis());
+        (film.to_framebuffer(), film.to_aov_buffers().unwrap())
+    }
 
-        let lense_pos = self.camera.position
-            + Vector3 {
-                x: 0.0,
-                y: 0.0,
-                z: 75.0,
-            };
-        let lense_size = (
-            self.camera.size.0 * self.camera.lens_factor.0,
-            self.camera.size.1 * self.camera.lens_factor.1,
-        );
+    fn render_film(&self, world: &World, snapshot_path: Option<&str>, aovs: bool) -> Film {
+        let timer = time::Instant::now();
 
-        let lense_ll = lense_pos
-            - Vector3 {
-                x: lense_size.0 / 2.0,
-                y: lense_size.1 / 2.0,
-                z: 0.0,
-            };
-        let lense_h = Vector3 {
-            x: lense_size.0,
-            y: 0.0,
-            z: 0.0,
+        let scheduler = TileScheduler::new(self.settings.image_size, self.settings.tile_size);
+        let film = Film::new(&scheduler, self.settings.image_size, aovs);
+
+        // A non-progressive render is a single pass with every sample
+        let target = self.settings.samples_per_pixel.max(1);
+        let (pass_size, time_budget, snapshot_interval) = match &self.settings.progressive {
+            Some(p) => (p.samples_per_pass.max(1), p.time_budget, Some(p.snapshot_interval)),
+            None => (target, None, None),
         };
-        let lense_v = Vector3 {
-            x: 0.0,
-            y: lense_size.1,
-            z: 0.0,
+        let passes = target.div_ceil(pass_size);
+
+        let mut progress = if quiet() {
+            None
+        } else {
+            let mut bar = ProgressBar::new(scheduler.tile_count() as u64 * passes as u64);
+            bar.message("Tiles: ");
+            Some(bar)
         };
-        
-        let num_pixels = img.enumerate_pixels().len() as i32;
-
-        let arc_world = Arc::new(world);
-        let arc_self = Arc::new(self);
-
-        let num_threads = 12_usize;
-        let mut rays: Vec<Vec<_>> = (0..num_threads).into_iter().map(|_|Vec::new()).collect();
-        let mut threads = Vec::new();
-
-        let chunk_size = num_pixels as usize / num_threads;
-
-        for (i, (x, y, p)) in img.enumerate_pixels_mut().into_iter().enumerate() {
-            let thread_index = i / chunk_size;
-
-            let camera_point = arc_self.camera.position;
-
-            let lense_point = lense_ll
-                + (x as f64 / arc_self.settings.image_size.0 as f64) * lense_h
-                + (y as f64 / arc_self.settings.image_size.1 as f64) * lense_v;
-            let dir = InnerSpace::normalize(lense_point - camera_point);
-
-            // Transform camera
-            // let mtx = cgmath::Matrix4::from_translation(Vector3{})
-
-            let ray = Ray {
-                origin: camera_point,
-                direction: dir,
-                bounce: 0,
-            };
+        let mut last_snapshot = time::Instant::now();
+
+        let mut done = 0;
+        while done < target {
+            let pass = done..(done + pass_size).min(target);
+            scheduler.run(
+                self.settings.threads,
+                |tile| film.accumulate(tile, |x, y, pixel, aovs| {
+                    self.sample_pixel(x, y, pass.clone(), world, pixel, aovs)
+                }),
+                |_tile| {
+                    if let Some(bar) = progress.as_mut() {
+                        bar.inc();
+                    }
+                    if let (Some(interval), Some(path)) = (snapshot_interval, snapshot_path) {
+                        if last_snapshot.elapsed() >= interval {
+                            if let Err(e) = film.to_framebuffer().save(path, &self.settings.output) {
+                                eprintln!("Snapshot failed: {:#}", e);
+                            }
+                            last_snapshot = time::Instant::now();
+                        }
+                    }
+                },
+            );
+            done = pass.end;
 
-            let arc_world = arc_world.clone();
-            let arc_self = arc_self.clone();
+            if let Some(budget) = time_budget {
+                if timer.elapsed() >= budget && done < target {
+                    statusln!("\nTime budget reached after {} samples per pixel", done);
+                    break;
+                }
+            }
+        }
+        if let Some(mut bar) = progress {
+            bar.finish();
+            println!();
+        }
+        film
+    }
 
-            struct Bad(*mut Rgb<u8>);
-            unsafe impl Send for Bad {}
-            let p = Bad(p);
+    // Fires samples_per_pixel jittered rays over the filter's support, one per stratum
+    pub fn render_pixel(&self, x: u32, y: u32, world: &World) -> Vector3<f64> {
+        let mut pixel = PixelAccumulator::new();
+        self.sample_pixel(x, y, 0..self.settings.samples_per_pixel.max(1), world, &mut pixel, None);
+        pixel.resolve()
+    }
 
-            rays[thread_index].push(
-                move || unsafe {*p.0 = vec_rgb(arc_self.cast(&ray, &arc_world))}
+    // Adds the given sample indices of a pixel. The strata come from samples_per_pixel, so
+    // rendering the indices in several batches gives the same samples as all at once.
+    pub fn sample_pixel(
+        &self,
+        x: u32,
+        y: u32,
+        samples: Range<u32>,
+        world: &World,
+        pixel: &mut PixelAccumulator,
+        mut aovs: Option<&mut AovAccumulator>,
+    ) {
+        let radius = self.settings.filter.radius();
+        let center = (x as f64 + 0.5, y as f64 + 0.5);
+
+        for i in samples {
+            sampling::seed_pixel(self.settings.seed, x, y, i);
+            let (jitter_x, jitter_y) = sampling::stratified(i, self.settings.samples_per_pixel);
+            let offset = ((2. * jitter_x - 1.) * radius, (2. * jitter_y - 1.) * radius);
+
+            let ray = self.camera.get_ray(
+                (center.0 + offset.0) / self.settings.image_size.0 as f64,
+                (center.1 + offset.1) / self.settings.image_size.1 as f64,
             );
+            let color = match aovs.as_deref_mut() {
+                Some(aovs) => {
+                    let (color, sample) = self.cast_primary(&ray, world);
+                    aovs.add(&sample, offset.0.hypot(offset.1));
+                    color
+                }
+                None => self.cast(&ray, world),
+            };
+            pixel.add(color, self.settings.filter.evaluate(offset.0, offset.1));
         }
+    }
 
-        for ray in rays {
-            threads.push(thread::spawn(
-                move || ray.into_iter().for_each(|a| {a();})
-            ));
+    // Radiance along a ray, with the integrator of the settings. Behaviors use this for the
+    // secondary rays of Whitted shading.
+    pub fn cast(&self, ray: &Ray, world: &World) -> Vector3<f64> {
+        if let Integrator::PathTracing(path) = &self.settings.integrator {
+            return integrator::trace_path(self, ray, world, path);
         }
-
-        let mut i = 0f32;
-        for thread in threads {
-            thread.join().unwrap();
-            println!("Progress: {}%", i * 100.0);
-            i += 1. / num_threads as f32;
+        match world.intersect(ray) {
+            Some((_, result)) => self.shade(ray, world, &result),
+            None => self.sky(ray, world),
         }
-
-        match img.save(output) {
-            Ok(_) => println!("Saved!"),
-            Err(e) => println!("{}", e),
-        };
-        println!("\n");
-
-        let duration = timer.elapsed();
-        println!("Finished in {}ms", duration.as_millis());
     }
 
-    pub fn cast(&self, ray: &Ray, world: &World) -> Vector3<f64> {
-        let mut min_distance = f64::MAX;
-        let mut closest_collision = None;
-        for entity in world.entities.iter() {
-            let ent = entity.as_ref();
-            let result = ent.collide(ray);
-            if result.collision {
-                let collision_dist = (result.position - ray.origin).magnitude2();
-                if collision_dist < min_distance {
-                    min_distance = collision_dist;
-                    closest_collision = Some(result);
-                }
+    // Casts a camera ray and also returns the AOVs of what it hit
+    fn cast_primary(&self, ray: &Ray, world: &World) -> (Vector3<f64>, AovSample) {
+        match world.intersect(ray) {
+            Some((index, result)) => {
+                let material = result.material.as_ref().unwrap();
+                let sample = AovSample {
+                    coverage: 1.,
+                    depth: self.camera.depth(result.position),
+                    normal: result.normal,
+                    albedo: material.albedo(&result),
+                    position: result.position.to_vec(),
+                    entity_id: index as u32 + 1,
+                    material_id: material.id,
+                };
+                let color = match &self.settings.integrator {
+                    Integrator::Whitted => self.shade(ray, world, &result),
+                    Integrator::PathTracing(path) => integrator::trace_path(self, ray, world, path),
+                };
+                (color, sample)
             }
+            None => (self.sky(ray, world), AovSample::background()),
         }
+    }
 
-        if let Some(result) = closest_collision {
-            let material = result.material.as_ref().unwrap();
-            let mut final_color: Vector3<f64> = material.color * world.ambient;
-            for behavior in material.shaders.iter() {
-                match behavior.as_ref().compute(ray, world, &result, self) {
-                    Some(color) => {
-                        final_color += color * behavior.mix();
-                    }
-                    None => continue,
+    fn shade(&self, ray: &Ray, world: &World, result: &ColliderResult) -> Vector3<f64> {
+        let material = result.material.as_ref().unwrap();
+        // Transmissive materials see the ambient light of whatever is behind them instead
+        let mut final_color: Vector3<f64> = if material.transmissive {
+            Vector3 {x: 0., y: 0., z: 0.}
+        } else {
+            material.albedo(result) * world.ambient
+        };
+        for behavior in material.shaders.iter() {
+            match behavior.as_ref().compute(ray, world, result, self) {
+                Some(color) => {
+                    final_color += color * behavior.mix();
                 }
+                None => continue,
             }
-            return final_color;
         }
-        
-        // Sky
+        final_color
+    }
+
+    pub fn sky(&self, ray: &Ray, world: &World) -> Vector3<f64> {
         match world.sky.shaders[0].compute(ray, world, &ColliderResult::negative(), self) {
             Some(color) => color,
             None => color_vec(178, 222, 236),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::behavior::reflection::ReflectionBehavior;
+    use crate::filter::GaussianFilter;
+    use crate::geometry::sphere::Sphere;
+    use crate::integrator::PathSettings;
+    use cgmath::InnerSpace;
+    use std::sync::Arc;
+
+    // Sky brightening towards -Y, the up of the default camera
+    struct TestSky;
+
+    impl RayBehavior for TestSky {
+        fn compute(&self, ray: &Ray, _world: &World, _collision: &ColliderResult, _tracer: &RayTracer) -> Option<Vector3<f64>> {
+            let t = 0.5 - 0.5 * ray.direction.normalize().y;
+            Some(Vector3 {x: 0.2 + t, y: 0.3 + t, z: 0.5 + t})
+        }
+
+        fn mix(&self) -> f64 {
+            1.
+        }
+    }
+
+    fn world() -> World {
+        let matte = Material::new_lambert_material(color_vec(200, 80, 60), 0.8, 1., 0.2, 0.5, 20);
+        let glossy = Material::new_glossy_lambert_material(
+            color_vec(90, 160, 220), None, 0.8, 1., ReflectionBehavior::new_glossy(0.5, 0.3, 2), 0.3, 40,
+        );
+        World {
+            entities: vec![
+                Box::new(Sphere::new(Point3 {x: -0.6, y: 0., z: 4.}, 0.7, matte)),
+                Box::new(Sphere::new(Point3 {x: 0.7, y: 0.2, z: 5.}, 0.8, glossy)),
+            ],
+            light_sources: vec![Box::new(PointLight::new(Point3 {x: 2., y: -3., z: 1.}, color_vec(255, 255, 255), 20., 1.))],
+            sky: Material::new_sky_material_from(Arc::new(TestSky)),
+            ambient: 0.1,
+        }
+    }
+
+    fn render(integrator: Integrator, progressive: Option<u32>, threads: usize) -> Vec<Vector3<f32>> {
+        set_quiet(true);
+        let mut raytracer = RayTracer::new_default_renderer((12, 9));
+        let settings = raytracer.settings_mut();
+        settings.samples_per_pixel = 6;
+        settings.filter = Box::new(GaussianFilter::default());
+        settings.tile_size = 4;
+        settings.threads = threads;
+        settings.seed = 3;
+        settings.integrator = integrator;
+        settings.progressive = progressive.map(|samples_per_pass| {
+            ProgressiveSettings::new(samples_per_pass, time::Duration::from_secs(3600))
+        });
+        raytracer.render(&world()).pixels().to_vec()
+    }
+
+    #[test]
+    fn progressive_renders_match_single_pass_renders() {
+        for integrator in [Integrator::Whitted, Integrator::PathTracing(PathSettings::new(4))] {
+            let single_pass = render(integrator, None, 1);
+            for (samples_per_pass, threads) in [(4, 1), (4, 3), (1, 2), (5, 1)] {
+                let progressive = render(integrator, Some(samples_per_pass), threads);
+                assert!(
+                    progressive == single_pass,
+                    "{:?} with {} samples per pass on {} threads", integrator, samples_per_pass, threads,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+// Exit codes of the command line tool for scenes that can't be loaded
+
+use std::{fs, path::PathBuf, process::{Command, Stdio}};
+
+const BAD_INPUT: i32 = 2;
+
+const SCENE: &str = r#"
+[render]
+width = 8
+height = 8
+
+[camera]
+eye = [0.0, 0.0, 0.0]
+target = [0.0, 0.0, 1.0]
+up = [0.0, -1.0, 0.0]
+fov = 40.0
+
+[world]
+ambient = 0.1
+sky = "SKY"
+
+[materials.matte]
+type = "lambert"
+color = [200, 200, 200]
+albedo = 0.8
+lambert = 1.0
+reflective = 0.0
+phong = 0.0
+alpha = 20
+"#;
+
+fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("raytracer-cli-{}", std::process::id()))
+}
+
+// Writes the base scene with changes applied to a file of its own and returns the exit
+// code of rendering it to <name>.png
+fn render(name: &str, edit: impl Fn(String) -> String) -> Option<i32> {
+    render_with(name, edit, &format!("{}.png", name), &[])
+}
+
+// Output is a file name in the temporary folder
+fn render_with(name: &str, edit: impl Fn(String) -> String, output: &str, args: &[&str]) -> Option<i32> {
+    let dir = temp_dir();
+    fs::create_dir_all(&dir).unwrap();
+    let sky = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("cubemaps/hd_blue_sunset");
+    let scene = edit(SCENE.replace("SKY", &sky.to_string_lossy()));
+    let path = dir.join(format!("{}.toml", name));
+    fs::write(&path, scene).unwrap();
+    // The face uses a vertex that doesn't exist
+    fs::write(dir.join("broken.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 9\n").unwrap();
+    fs::write(dir.join("no_library.obj"), "mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n").unwrap();
+    Command::new(env!("CARGO_BIN_EXE_raytracer"))
+        .arg(&path)
+        .args(["-q", "-o"])
+        .arg(dir.join(output))
+        .args(args)
+        .stderr(Stdio::null())
+        .status()
+        .unwrap()
+        .code()
+}
+
+#[test]
+fn unknown_output_format_is_bad_input() {
+    assert_eq!(render_with("unknown_format", |s| s, "unknown_format.xyz", &[]), Some(BAD_INPUT));
+    assert_eq!(render_with("no_extension", |s| s, "no_extension", &[]), Some(BAD_INPUT));
+}
+
+#[test]
+fn invalid_toml_is_bad_input() {
+    assert_eq!(render("invalid_toml", |s| s + "[render"), Some(BAD_INPUT));
+}
+
+#[test]
+fn zero_width_is_bad_input() {
+    assert_eq!(render("zero_width", |s| s.replace("width = 8", "width = 0")), Some(BAD_INPUT));
+}
+
+#[test]
+fn negative_snapshot_interval_is_bad_input() {
+    let progressive = "height = 8\n[render.progressive]\nsamples_per_pass = 1\nsnapshot_interval = -1.0\n";
+    assert_eq!(render("negative_interval", |s| s.replacen("height = 8\n", progressive, 1)), Some(BAD_INPUT));
+}
+
+#[test]
+fn malformed_model_is_bad_input() {
+    let model = "\n[[models]]\npath = \"broken.obj\"\nposition = [0.0, 0.0, 5.0]\nmaterial = \"matte\"\n";
+    assert_eq!(render("malformed_model", |s| s + model), Some(BAD_INPUT));
+}
+
+#[test]
+fn missing_model_is_bad_input() {
+    let model = "\n[[models]]\npath = \"missing.obj\"\nposition = [0.0, 0.0, 5.0]\nmaterial = \"matte\"\n";
+    assert_eq!(render("missing_model", |s| s + model), Some(BAD_INPUT));
+}
+
+#[test]
+fn missing_material_library_falls_back() {
+    let model = "\n[[models]]\npath = \"no_library.obj\"\nposition = [0.0, 0.0, 5.0]\n";
+    assert_eq!(render("missing_library", |s| s + model), Some(0));
+}
+
+#[test]
+fn corrupt_cubemap_face_is_bad_input() {
+    let sky = temp_dir().join("corrupt_sky");
+    fs::create_dir_all(&sky).unwrap();
+    for face in &["left", "right", "front", "back", "up", "down"] {
+        fs::write(sky.join(format!("{}.png", face)), "not a png").unwrap();
+    }
+    let sky = sky.to_string_lossy().into_owned();
+    assert_eq!(
+        render("corrupt_sky", |s| s.lines()
+            .map(|line| if line.starts_with("sky = ") { format!("sky = \"{}\"", sky) } else { line.to_owned() })
+            .collect::<Vec<_>>()
+            .join("\n")),
+        Some(BAD_INPUT),
+    );
+}
+
+#[test]
+fn degenerate_cameras_are_bad_input() {
+    let target = "target = [0.0, 0.0, 1.0]";
+    assert_eq!(render("eye_is_target", |s| s.replace(target, "target = [0.0, 0.0, 0.0]")), Some(BAD_INPUT));
+    assert_eq!(render("zero_up", |s| s.replace("up = [0.0, -1.0, 0.0]", "up = [0.0, 0.0, 0.0]")), Some(BAD_INPUT));
+    assert_eq!(render("zero_fov", |s| s.replace("fov = 40.0", "fov = 0.0")), Some(BAD_INPUT));
+    assert_eq!(render("wide_fov", |s| s.replace("fov = 40.0", "fov = 180.0")), Some(BAD_INPUT));
+}
+
+#[test]
+fn invalid_lights_are_bad_input() {
+    let point = "\n[[lights]]\ntype = \"point\"\nposition = [0.0, 0.0, 0.0]\ncolor = [255, 255, 255]\n\
+        brightness = 1.0\nattenuation = 0.0\n";
+    assert_eq!(render("zero_attenuation", |s| s + point), Some(BAD_INPUT));
+    let spot = "\n[[lights]]\ntype = \"spot\"\nposition = [0.0, 0.0, 0.0]\ndirection = [0.0, 0.0, 1.0]\n\
+        inner_angle = 30.0\nouter_angle = 20.0\ncolor = [255, 255, 255]\nbrightness = 1.0\nattenuation = 1.0\n";
+    assert_eq!(render("inverted_spot", |s| s + spot), Some(BAD_INPUT));
+}
+
+#[test]
+fn zero_absorption_distance_is_bad_input() {
+    let glass = "\n[materials.glass]\ntype = \"dielectric\"\nabsorption_distance = 0.0\n";
+    assert_eq!(render("zero_absorption", |s| s + glass), Some(BAD_INPUT));
+}
+
+#[test]
+fn material_ids_are_the_same_every_run() {
+    let mut spheres = String::new();
+    for (i, name) in ["red", "green", "blue", "white"].iter().enumerate() {
+        spheres += &format!(
+            "\n[materials.{}]\ntype = \"lambert\"\ncolor = [200, 200, 200]\nalbedo = 0.8\nlambert = 1.0\n\
+             reflective = 0.0\nphong = 0.0\nalpha = 20\n\
+             \n[[spheres]]\nposition = [{:.1}, 0.0, 6.0]\nradius = 0.5\nmaterial = \"{}\"\n",
+            name, i as f64 - 1.5, name,
+        );
+    }
+    let ids: Vec<Vec<u8>> = (0..3)
+        .map(|run| {
+            let name = format!("material_ids_{}", run);
+            assert_eq!(render_with(&name, |s| s.clone() + &spheres, &format!("{}.png", name), &["--aov", "material_id"]), Some(0));
+            fs::read(temp_dir().join(format!("{}.material_id.png", name))).unwrap()
+        })
+        .collect();
+    assert!(ids.iter().all(|image| *image == ids[0]));
+}
+
+#[test]
+fn repeated_aovs_are_written_once() {
+    let args = ["--aov", "depth,normal,depth"];
+    assert_eq!(render_with("repeated_aovs", |s| s, "repeated_aovs.exr", &args), Some(0));
+}
+
+#[test]
+fn max_bounces_with_whitted_is_bad_input() {
+    let args = ["--integrator", "whitted", "--max-bounces", "3"];
+    assert_eq!(render_with("whitted_bounces", |s| s, "whitted_bounces.png", &args), Some(BAD_INPUT));
+}